@@ -1,9 +1,181 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A chat message, in Ollama's form. OpenAI content parts are accepted too:
+/// the text parts are joined into `content` and inline images moved to `images`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "WireMessage")]
 pub struct Message {
     pub role: String,
     pub content: String,
+    /// Proxy-issued HMAC over `content` for assistant turns; see `turn_signing`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// The call a `tool` message answers (OpenAI only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Base64 images, or the URLs of OpenAI `image_url` parts that were not
+    /// inline data; see `image_policy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+}
+
+/// `Message` as clients may send it.
+#[derive(Deserialize)]
+struct WireMessage {
+    role: String,
+    /// `null` for assistant turns that only call tools.
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    signature: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+    #[serde(default)]
+    images: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// An OpenAI content part.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Deserialize)]
+struct ImageUrl {
+    url: String,
+}
+
+impl From<WireMessage> for Message {
+    fn from(wire: WireMessage) -> Self {
+        let mut images = wire.images.unwrap_or_default();
+        let content = match wire.content {
+            None => String::new(),
+            Some(Content::Text(text)) => text,
+            Some(Content::Parts(parts)) => {
+                let mut texts = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => texts.push(text),
+                        ContentPart::ImageUrl { image_url } => images.push(data_url_payload(&image_url.url)),
+                    }
+                }
+                texts.join("\n")
+            }
+        };
+        Message {
+            role: wire.role,
+            content,
+            signature: wire.signature,
+            tool_calls: wire.tool_calls,
+            tool_call_id: wire.tool_call_id,
+            images: if images.is_empty() { None } else { Some(images) },
+        }
+    }
+}
+
+/// The base64 payload of a `data:<type>;base64,` URL; other URLs unchanged.
+fn data_url_payload(url: &str) -> String {
+    match url.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,")) {
+        Some((_, payload)) => payload.to_string(),
+        None => url.to_string(),
+    }
+}
+
+/// A tool the model may call. OpenAI and Ollama share this shape.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tool {
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionDef,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionDef {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// A call the model made. OpenAI encodes `arguments` as a JSON string and
+/// Ollama as an object; both are accepted, and `to_openai` / `to_ollama`
+/// convert between them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+impl FunctionCall {
+    /// The arguments as a JSON value, parsing them if they are a string.
+    /// `None` if they are a string that is not valid JSON.
+    pub fn parsed_arguments(&self) -> Option<Value> {
+        match &self.arguments {
+            Value::String(s) if s.trim().is_empty() => Some(Value::Object(Default::default())),
+            Value::String(s) => serde_json::from_str(s).ok(),
+            Value::Null => Some(Value::Object(Default::default())),
+            other => Some(other.clone()),
+        }
+    }
+}
+
+impl ToolCall {
+    /// OpenAI form: an id, `type: function` and string arguments.
+    pub fn to_openai(&self) -> ToolCall {
+        let arguments = match &self.function.arguments {
+            Value::String(s) => s.clone(),
+            Value::Null => "{}".to_string(),
+            other => other.to_string(),
+        };
+        ToolCall {
+            id: Some(self.id.clone().unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()))),
+            kind: Some(function_type()),
+            function: FunctionCall { name: self.function.name.clone(), arguments: Value::String(arguments) },
+        }
+    }
+
+    /// Ollama form: object arguments. Arguments that do not parse are sent
+    /// as an empty object, as Ollama rejects anything else.
+    pub fn to_ollama(&self) -> ToolCall {
+        ToolCall {
+            id: None,
+            kind: None,
+            function: FunctionCall {
+                name: self.function.name.clone(),
+                arguments: self.function.parsed_arguments().unwrap_or_else(|| Value::Object(Default::default())),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -11,6 +183,11 @@ pub struct ChatCompletionRequest {
     pub model: String,
     pub messages: Vec<Message>,
     pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    /// `"none"`, `"auto"`, `"required"` or `{"type": "function", "function": {"name": ...}}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,6 +263,7 @@ mod tests {
                     message: Message {
                         role: "assistant".to_string(),
                         content: "Hello there!".to_string(),
+                        ..Message::default()
                     },
                     finish_reason: Some("stop".to_string()),
                 }
@@ -99,5 +277,51 @@ mod tests {
         };
         let serialized = serde_json::to_string(&response).unwrap();
         assert!(serialized.contains("Hello there!"));
+        assert!(!serialized.contains("tool_calls"));
+    }
+
+    #[test]
+    fn test_tool_call_formats() {
+        let data = json!({
+            "model": "m",
+            "messages": [
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}],
+            "tool_choice": "auto"
+        });
+        let request: ChatCompletionRequest = serde_json::from_value(data).unwrap();
+        assert_eq!(request.messages[0].content, "");
+        assert_eq!(request.messages[1].tool_call_id.as_deref(), Some("call_1"));
+
+        let call = &request.messages[0].tool_calls.as_ref().unwrap()[0];
+        let ollama = call.to_ollama();
+        assert_eq!(serde_json::to_value(&ollama).unwrap(), json!({"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}));
+
+        let openai = ollama.to_openai();
+        assert!(openai.id.unwrap().starts_with("call_"));
+        assert_eq!(openai.function.arguments, json!("{\"city\":\"Paris\"}"));
+    }
+
+    #[test]
+    fn test_content_parts_mapped_to_images() {
+        let message: Message = serde_json::from_value(json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo=", "detail": "low"}},
+                {"type": "text", "text": "Be brief."},
+                {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+            ]
+        })).unwrap();
+        assert_eq!(message.content, "What is this?\nBe brief.");
+        assert_eq!(message.images.as_deref().unwrap(), ["iVBORw0KGgo=", "https://example.com/cat.png"]);
+
+        let ollama = serde_json::to_value(&message).unwrap();
+        assert_eq!(ollama["content"], "What is this?\nBe brief.");
+        assert_eq!(ollama["images"][0], "iVBORw0KGgo=");
     }
 }
@@ -0,0 +1,30 @@
+use molt_guard::{embedding_guard::EmbeddingIndex, ollama_client::OllamaClient};
+use std::path::Path;
+
+/// Rebuilds the jailbreak embedding index from a JSONL corpus.
+///
+/// Usage: molt-guard-index <corpus.jsonl> <index.json>
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <corpus.jsonl> <index.json>", args[0]);
+        eprintln!("Each corpus line is {{\"text\": \"...\", \"category\": \"...\"}}; category is optional.");
+        std::process::exit(2);
+    }
+
+    let ollama_url = std::env::var("OLLAMA_URL")
+        .unwrap_or_else(|_| "http://ollama:11434".to_string());
+    let embedding_model = std::env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "nomic-embed-text:latest".to_string());
+
+    let corpus = EmbeddingIndex::read_corpus(Path::new(&args[1]))?;
+    println!("Embedding {} prompts with {} via {}", corpus.len(), embedding_model, ollama_url);
+
+    let client = OllamaClient::new(&ollama_url);
+    client.ensure_model_exists(&embedding_model).await?;
+    let index = EmbeddingIndex::build(&client, &embedding_model, corpus).await?;
+    index.save(Path::new(&args[2]))?;
+    println!("Wrote {} entries to {}", index.entries.len(), args[2]);
+
+    Ok(())
+}
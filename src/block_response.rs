@@ -0,0 +1,131 @@
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+/// How refused requests are reported to the client.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockMode {
+    /// An ordinary reply (HTTP 200) whose text explains the refusal.
+    #[default]
+    Message,
+    /// An API error with a status code: an OpenAI error object on
+    /// `/v1/chat/completions`, `{"error": "..."}` on the Ollama endpoints.
+    Error,
+}
+
+/// Per-profile reporting of blocks.
+///
+/// ```json
+/// { "block_response": { "mode": "message", "message_template": "Request refused: {reason}" } }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BlockResponseConfig {
+    pub mode: BlockMode,
+    /// Text of in-band refusals; `{reason}` is replaced with the reason.
+    /// Defaults to the Molt-Guard security alert.
+    pub message_template: Option<String>,
+}
+
+impl BlockResponseConfig {
+    /// The in-band text for a refusal or a withheld reply.
+    pub fn message(&self, reason: &str) -> String {
+        match &self.message_template {
+            Some(template) => template.replace("{reason}", reason),
+            None => security_status_message(reason),
+        }
+    }
+}
+
+/// Text shown in place of the assistant reply when a request is refused.
+fn security_status_message(error_msg: &str) -> String {
+    if error_msg.contains("provisioned") {
+        format!("⏳ **Molt-Guard Security Status**: {}", error_msg)
+    } else {
+        format!("🛡️ **Molt-Guard Security Alert**: {}", error_msg)
+    }
+}
+
+/// Why a request was refused, which decides the status code in `error` mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockKind {
+    /// The request carries content the profile does not accept (input
+    /// filters, image policy).
+    Policy,
+    /// The guard judged the prompt or an image malicious.
+    Injection,
+    /// The history holds assistant turns this proxy did not issue.
+    ForgedTurn,
+    /// The conversation was suspended after repeated suspicious requests.
+    SessionSuspended,
+    /// A guard model or the backend could not be reached or is still loading.
+    Unavailable,
+}
+
+impl BlockKind {
+    /// The kind of a guard error. Guard verdicts start with `Security block`;
+    /// anything else means the guard itself failed.
+    pub fn of_guard_error(error_msg: &str) -> Self {
+        if !error_msg.starts_with("Security block") {
+            BlockKind::Unavailable
+        } else if error_msg.contains("suspended") {
+            BlockKind::SessionSuspended
+        } else {
+            BlockKind::Injection
+        }
+    }
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            BlockKind::Policy => StatusCode::BAD_REQUEST,
+            BlockKind::Injection | BlockKind::ForgedTurn => StatusCode::FORBIDDEN,
+            BlockKind::SessionSuspended => StatusCode::TOO_MANY_REQUESTS,
+            BlockKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// OpenAI error `type` and `code`.
+    pub fn error_type(self) -> (&'static str, &'static str) {
+        match self {
+            BlockKind::Policy => ("invalid_request_error", "content_policy_violation"),
+            BlockKind::Injection => ("permission_error", "prompt_injection_detected"),
+            BlockKind::ForgedTurn => ("permission_error", "forged_assistant_turn"),
+            BlockKind::SessionSuspended => ("rate_limit_error", "session_suspended"),
+            BlockKind::Unavailable => ("server_error", "service_unavailable"),
+        }
+    }
+
+    /// An OpenAI error object for this kind of block.
+    pub fn openai_error(self, error_msg: &str) -> serde_json::Value {
+        let (kind, code) = self.error_type();
+        serde_json::json!({
+            "error": { "message": error_msg, "type": kind, "param": null, "code": code }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let default = BlockResponseConfig::default();
+        assert_eq!(default.message("Security block: no."), "🛡️ **Molt-Guard Security Alert**: Security block: no.");
+        assert!(default.message("The model is being provisioned").starts_with("⏳"));
+
+        let templated = BlockResponseConfig { message_template: Some("Refused ({reason})".to_string()), ..default };
+        assert_eq!(templated.message("too long"), "Refused (too long)");
+    }
+
+    #[test]
+    fn test_guard_errors_classified() {
+        assert_eq!(BlockKind::of_guard_error("Security block: Malicious prompt detected"), BlockKind::Injection);
+        assert_eq!(BlockKind::of_guard_error("Security block: this conversation has been suspended"), BlockKind::SessionSuspended);
+        assert_eq!(BlockKind::of_guard_error("The specialized security model (x) is currently being provisioned"), BlockKind::Unavailable);
+
+        let error = BlockKind::SessionSuspended.openai_error("slow down");
+        assert_eq!(error["error"]["type"], "rate_limit_error");
+        assert_eq!(BlockKind::SessionSuspended.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::filters::{Filter, Finding};
+use aho_corasick::AhoCorasick;
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// What happens when a custom detector matches.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectorAction {
+    /// Replace the match with the detector's placeholder.
+    #[default]
+    Redact,
+    /// Refuse the whole request or response.
+    Block,
+    /// Leave the text alone but log the match.
+    Audit,
+}
+
+/// One user-defined detector: a list of terms, a regex, or both.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DetectorSpec {
+    /// Reported as the finding kind, e.g. `project_codename`.
+    pub name: String,
+    /// Exact terms, matched case-insensitively on word boundaries.
+    #[serde(default)]
+    pub terms: Vec<String>,
+    /// File with one term per line (`#` starts a comment), relative to the
+    /// detector file.
+    pub terms_file: Option<PathBuf>,
+    pub regex: Option<String>,
+    /// Replacement text; `[REDACTED]` if unset.
+    pub placeholder: Option<String>,
+    #[serde(default)]
+    pub action: DetectorAction,
+}
+
+/// A detector file.
+///
+/// ```json
+/// {
+///   "detectors": [
+///     { "name": "project_codename", "terms": ["Bluebird", "Nightjar"], "placeholder": "[PROJECT]" },
+///     { "name": "internal_host", "terms_file": "hosts.txt", "action": "block" },
+///     { "name": "ticket", "regex": "\\bOPS-\\d+\\b", "action": "audit" }
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct DetectorFile {
+    pub detectors: Vec<DetectorSpec>,
+}
+
+/// Compiled form of a detector file.
+struct Detectors {
+    specs: Vec<DetectorSpec>,
+    /// All terms of all detectors in one automaton; `term_owner[i]` is the
+    /// detector of pattern `i`.
+    terms: Option<AhoCorasick>,
+    term_owner: Vec<usize>,
+    regexes: Vec<(Regex, usize)>,
+}
+
+impl Detectors {
+    fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let file: DetectorFile = serde_json::from_str(&data).with_context(|| format!("parsing {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        let mut terms = Vec::new();
+        let mut term_owner = Vec::new();
+        let mut regexes = Vec::new();
+        for (i, spec) in file.detectors.iter().enumerate() {
+            let mut spec_terms = spec.terms.clone();
+            if let Some(terms_file) = &spec.terms_file {
+                let terms_path = base.join(terms_file);
+                let list = std::fs::read_to_string(&terms_path).with_context(|| format!("reading {}", terms_path.display()))?;
+                spec_terms.extend(list.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(String::from));
+            }
+            if spec_terms.is_empty() && spec.regex.is_none() {
+                return Err(anyhow!("detector '{}' has neither terms nor a regex", spec.name));
+            }
+            term_owner.extend(std::iter::repeat_n(i, spec_terms.len()));
+            terms.extend(spec_terms.iter().map(|t| lowercase(t).0));
+            if let Some(regex) = &spec.regex {
+                let regex = Regex::new(regex).with_context(|| format!("detector '{}'", spec.name))?;
+                regexes.push((regex, i));
+            }
+        }
+
+        let terms = match terms.is_empty() {
+            true => None,
+            false => Some(AhoCorasick::new(&terms)?),
+        };
+        Ok(Self { specs: file.detectors, terms, term_owner, regexes })
+    }
+
+    /// Matches in priority order: detector order, then position, longer
+    /// terms before shorter ones starting at the same place.
+    fn scan(&self, filter: &str, input: &str) -> Vec<Finding> {
+        let mut matches: Vec<(usize, usize, usize)> = Vec::new();
+        if let Some(terms) = &self.terms {
+            // Terms are lowercased too, so any script matches regardless of case.
+            let (lower, offsets) = lowercase(input);
+            // Overlapping search so a term failing the boundary check does not
+            // hide a shorter or longer one at the same position.
+            for m in terms.find_overlapping_iter(&lower) {
+                let (start, end) = (offsets[m.start()], offsets[m.end()]);
+                if start < end && on_word_boundaries(input, start, end) {
+                    matches.push((self.term_owner[m.pattern().as_usize()], start, end));
+                }
+            }
+        }
+        for (regex, owner) in &self.regexes {
+            matches.extend(regex.find_iter(input).map(|m| (*owner, m.start(), m.end())));
+        }
+        matches.sort_by_key(|&(owner, start, end)| (owner, start, std::cmp::Reverse(end)));
+
+        matches.into_iter()
+            .map(|(owner, start, end)| Finding {
+                filter: filter.to_string(),
+                kind: self.specs[owner].name.clone(),
+                start,
+                end,
+                confidence: 1.0,
+            })
+            .collect()
+    }
+
+    fn spec(&self, kind: &str) -> Option<&DetectorSpec> {
+        self.specs.iter().find(|s| s.name == kind)
+    }
+}
+
+/// `input` lowercased one character at a time, with the offset in `input`
+/// of every byte of the result and of its end: lowercasing may change how
+/// many bytes a character takes.
+fn lowercase(input: &str) -> (String, Vec<usize>) {
+    let mut lower = String::with_capacity(input.len());
+    let mut offsets = Vec::with_capacity(input.len() + 1);
+    for (i, c) in input.char_indices() {
+        lower.extend(c.to_lowercase());
+        offsets.resize(lower.len(), i);
+    }
+    offsets.push(input.len());
+    (lower, offsets)
+}
+
+fn on_word_boundaries(input: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    !input[..start].chars().next_back().is_some_and(is_word) && !input[end..].chars().next().is_some_and(is_word)
+}
+
+/// Detectors read from a filter's file but not in use yet.
+pub struct StagedDetectors(Detectors);
+
+impl StagedDetectors {
+    /// Names of the staged detectors.
+    pub fn kinds(&self) -> Vec<String> {
+        self.0.specs.iter().map(|s| s.name.clone()).collect()
+    }
+}
+
+/// A filter built from a user-supplied detector file. The file can be
+/// reloaded while the proxy runs; pipelines sharing the filter pick up the
+/// new detectors on their next request.
+pub struct CustomFilter {
+    name: String,
+    path: PathBuf,
+    detectors: RwLock<Arc<Detectors>>,
+}
+
+impl CustomFilter {
+    pub fn load(name: &str, path: &Path) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            detectors: RwLock::new(Arc::new(Detectors::load(path).with_context(|| format!("filter '{}'", name))?)),
+        })
+    }
+
+    /// Re-reads the detector file. On error the previous detectors stay active.
+    pub fn reload(&self) -> Result<()> {
+        let staged = self.stage()?;
+        self.activate(staged);
+        Ok(())
+    }
+
+    /// Re-reads the detector file without switching to it, so the new
+    /// detectors can be checked first.
+    pub fn stage(&self) -> Result<StagedDetectors> {
+        Ok(StagedDetectors(Detectors::load(&self.path).with_context(|| format!("filter '{}'", self.name))?))
+    }
+
+    /// Switches to detectors read by `stage`.
+    pub fn activate(&self, staged: StagedDetectors) {
+        *self.detectors.write().unwrap() = Arc::new(staged.0);
+    }
+
+    /// Names of the detectors currently loaded.
+    pub fn kinds(&self) -> Vec<String> {
+        self.current().specs.iter().map(|s| s.name.clone()).collect()
+    }
+
+    fn current(&self) -> Arc<Detectors> {
+        self.detectors.read().unwrap().clone()
+    }
+}
+
+impl Filter for CustomFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn scan(&self, input: &str) -> Vec<Finding> {
+        let detectors = self.current();
+        let mut audited: HashMap<String, usize> = HashMap::new();
+        let findings = detectors.scan(&self.name, input)
+            .into_iter()
+            .filter(|f| match detectors.spec(&f.kind).map(|s| s.action) {
+                Some(DetectorAction::Audit) => {
+                    *audited.entry(f.kind.clone()).or_default() += 1;
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        for (kind, count) in audited {
+            println!("!!! CUSTOM DETECTOR AUDIT: '{}' matched {} time(s) (filter {})", kind, count, self.name);
+        }
+        findings
+    }
+
+    fn placeholder(&self, finding: &Finding, _value: &str) -> String {
+        self.current().spec(&finding.kind)
+            .and_then(|s| s.placeholder.clone())
+            .unwrap_or_else(|| "[REDACTED]".to_string())
+    }
+
+    fn blocks(&self, finding: &Finding) -> bool {
+        self.current().spec(&finding.kind).is_some_and(|s| s.action == DetectorAction::Block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_detectors(dir: &Path, json: serde_json::Value) -> PathBuf {
+        let path = dir.join("detectors.json");
+        std::fs::write(&path, json.to_string()).unwrap();
+        path
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("molt-guard-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_terms_match_case_insensitively_on_word_boundaries() {
+        let dir = temp_dir("terms");
+        std::fs::write(dir.join("hosts.txt"), "# internal hosts\ndb01.corp.local\n\nbuild.corp.local\n").unwrap();
+        let path = write_detectors(&dir, serde_json::json!({ "detectors": [
+            { "name": "project", "terms": ["Bluebird", "Bluebird Two"], "placeholder": "[PROJECT]" },
+            { "name": "host", "terms_file": "hosts.txt" },
+            { "name": "ticket", "regex": "\\bOPS-\\d+\\b", "placeholder": "[TICKET]" }
+        ]}));
+        let filter = CustomFilter::load("corp", &path).unwrap();
+
+        let redaction = filter.apply("BLUEBIRD two ships from DB01.corp.local (OPS-42), not bluebirds.");
+        assert_eq!(redaction.text, "[PROJECT] ships from [REDACTED] ([TICKET]), not bluebirds.");
+        assert_eq!(filter.kinds(), vec!["project", "host", "ticket"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_terms_match_case_insensitively_beyond_ascii() {
+        let dir = temp_dir("unicode");
+        let path = write_detectors(&dir, serde_json::json!({ "detectors": [
+            { "name": "customer", "terms": ["Müller", "Société Générale"], "placeholder": "[CUSTOMER]" }
+        ]}));
+        let filter = CustomFilter::load("corp", &path).unwrap();
+
+        let redaction = filter.apply("MÜLLER banks with SOCIÉTÉ GÉNÉRALE, not Müllerei.");
+        assert_eq!(redaction.text, "[CUSTOMER] banks with [CUSTOMER], not Müllerei.");
+        // `İ` lowercases to two characters; offsets still fit the original text.
+        assert_eq!(filter.apply("İ MÜLLER").text, "İ [CUSTOMER]");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_actions() {
+        let dir = temp_dir("actions");
+        let path = write_detectors(&dir, serde_json::json!({ "detectors": [
+            { "name": "codename", "terms": ["Nightjar"], "action": "block" },
+            { "name": "customer", "terms": ["Acme"], "action": "audit" }
+        ]}));
+        let filter = CustomFilter::load("corp", &path).unwrap();
+
+        let findings = filter.scan("Acme asked about Nightjar");
+        assert_eq!(findings.len(), 1);
+        assert!(filter.blocks(&findings[0]));
+        assert!(filter.scan("Acme only").is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_keeps_old_detectors_on_error() {
+        let dir = temp_dir("reload");
+        let path = write_detectors(&dir, serde_json::json!({ "detectors": [{ "name": "a", "terms": ["alpha"] }] }));
+        let filter = CustomFilter::load("corp", &path).unwrap();
+        assert_eq!(filter.scan("alpha beta").len(), 1);
+
+        write_detectors(&dir, serde_json::json!({ "detectors": [{ "name": "b", "terms": ["beta"] }] }));
+        filter.reload().unwrap();
+        assert_eq!(filter.scan("alpha beta")[0].kind, "b");
+
+        write_detectors(&dir, serde_json::json!({ "detectors": [{ "name": "c", "regex": "(" }] }));
+        assert!(filter.reload().is_err());
+        assert_eq!(filter.scan("alpha beta")[0].kind, "b");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
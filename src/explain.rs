@@ -0,0 +1,145 @@
+use axum::http::{HeaderMap, HeaderValue};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+
+/// Request header asking for the verdict of every stage in the
+/// `X-Molt-Guard-Explain` response header.
+pub const EXPLAIN_HEADER: &str = "x-molt-guard-explain";
+/// Request header asking for every check to run without forwarding the
+/// request; the explanation is returned as the response body.
+pub const DRY_RUN_HEADER: &str = "x-molt-guard-dry-run";
+
+/// Whether a client set one of the headers above (`1` or `true`).
+pub fn requested(headers: &HeaderMap, name: &str) -> bool {
+    headers.get(name)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// What a stage did to a request or reply, in increasing severity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageVerdict {
+    Passed,
+    Modified,
+    Blocked,
+}
+
+#[derive(Serialize)]
+struct StageReport {
+    stage: String,
+    verdict: StageVerdict,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    details: Map<String, Value>,
+}
+
+/// The verdicts of one request's stages, in the order they ran, with the
+/// scores and findings behind them. Never holds matched values.
+pub struct Explanation {
+    profile: String,
+    shadow: bool,
+    pub dry_run: bool,
+    stages: Mutex<Vec<StageReport>>,
+}
+
+impl Explanation {
+    pub fn new(profile: &str, shadow: bool, dry_run: bool) -> Self {
+        Self { profile: profile.to_string(), shadow, dry_run, stages: Mutex::new(Vec::new()) }
+    }
+
+    /// Records a stage's verdict. A stage that runs more than once keeps its
+    /// most severe verdict.
+    pub fn record(&self, stage: &str, verdict: StageVerdict, reason: Option<&str>) {
+        self.update(stage, |report| {
+            if verdict > report.verdict || report.reason.is_none() && verdict == report.verdict {
+                report.verdict = verdict;
+                report.reason = reason.map(str::to_string);
+            }
+        });
+    }
+
+    /// Attaches a detail (score, findings) to a stage.
+    pub fn detail(&self, stage: &str, key: &str, value: impl Serialize) {
+        let value = serde_json::to_value(value).unwrap_or_default();
+        self.update(stage, |report| {
+            report.details.insert(key.to_string(), value);
+        });
+    }
+
+    fn update(&self, stage: &str, update: impl FnOnce(&mut StageReport)) {
+        let mut stages = self.stages.lock().unwrap();
+        let index = match stages.iter().position(|s| s.stage == stage) {
+            Some(index) => index,
+            None => {
+                stages.push(StageReport { stage: stage.to_string(), verdict: StageVerdict::Passed, reason: None, details: Map::new() });
+                stages.len() - 1
+            }
+        };
+        update(&mut stages[index]);
+    }
+
+    pub fn to_json(&self) -> Value {
+        let stages = self.stages.lock().unwrap();
+        let verdict = stages.iter().map(|s| s.verdict).max().unwrap_or(StageVerdict::Passed);
+        serde_json::json!({
+            "profile": self.profile,
+            "shadow": self.shadow,
+            "dry_run": self.dry_run,
+            "verdict": verdict,
+            "stages": *stages,
+        })
+    }
+
+    /// `to_json` as a header value, with non-ASCII characters escaped.
+    pub fn header_value(&self) -> HeaderValue {
+        let json = self.to_json().to_string();
+        let mut ascii = String::with_capacity(json.len());
+        for c in json.chars() {
+            if c.is_ascii() {
+                ascii.push(c);
+            } else {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    ascii.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+        HeaderValue::from_str(&ascii).unwrap_or_else(|_| HeaderValue::from_static("{}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explanation_keeps_most_severe_verdict() {
+        let explanation = Explanation::new("default", false, true);
+        explanation.record("input_filters", StageVerdict::Passed, None);
+        explanation.detail("guard", "score", 0.92);
+        explanation.record("guard", StageVerdict::Blocked, Some("Security block: jailbreak"));
+        explanation.record("guard", StageVerdict::Passed, None);
+
+        assert_eq!(explanation.to_json(), serde_json::json!({
+            "profile": "default",
+            "shadow": false,
+            "dry_run": true,
+            "verdict": "blocked",
+            "stages": [
+                { "stage": "input_filters", "verdict": "passed" },
+                { "stage": "guard", "verdict": "blocked", "reason": "Security block: jailbreak", "details": { "score": 0.92 } },
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_header_value_escapes_non_ascii() {
+        let explanation = Explanation::new("default", false, false);
+        explanation.record("output_filters", StageVerdict::Blocked, Some("🛡️ withheld"));
+        let header = explanation.header_value();
+        let parsed: Value = serde_json::from_str(header.to_str().unwrap()).unwrap();
+        assert_eq!(parsed["stages"][0]["reason"], "🛡️ withheld");
+    }
+}
@@ -1,42 +1,100 @@
+pub mod classifier;
+pub mod command_filter;
+pub mod custom_filter;
+pub mod embedding_guard;
+pub mod explain;
+pub mod local_model;
 pub mod prompt_guard;
 pub mod middleware;
 pub mod secrets_filter;
 pub mod pii_filter;
+pub mod pii_locales;
+pub mod pii_vault;
 pub mod api_types;
+pub mod block_response;
 pub mod ollama_client;
+pub mod package_filter;
+pub mod filters;
+pub mod image_policy;
+pub mod input_dlp;
+pub mod link_filter;
+pub mod pattern_engine;
+pub mod profiles;
+pub mod prompt_leak;
+pub mod redaction_style;
+pub mod session_risk;
+pub mod shadow;
+pub mod tool_policy;
+pub mod turn_signing;
+pub mod vision_guard;
 
 use axum::{
     routing::{get, post},
     Router,
     Json,
-    extract::State,
+    Extension,
+    extract::{DefaultBodyLimit, Request, State},
+    middleware::Next,
     http::{StatusCode, Method, HeaderMap},
     response::{Response, IntoResponse},
 };
-use crate::api_types::{ChatCompletionRequest, ChatCompletionResponse, Message, Choice, ListModelsResponse, ModelObject};
-use crate::prompt_guard::{PromptGuardClient, ValidationMode, Sensitivity};
+use crate::api_types::{ChatCompletionRequest, ChatCompletionResponse, Message, Choice, ListModelsResponse, ModelObject, Tool, ToolCall};
+use crate::block_response::{BlockKind, BlockMode};
+use crate::classifier::Classifier;
 use crate::middleware::InputValidationMiddleware;
-use crate::secrets_filter::SecretsFilter;
-use crate::pii_filter::PiiFilter;
+use crate::explain::{Explanation, EXPLAIN_HEADER, DRY_RUN_HEADER};
+use crate::shadow::{Audit, ShadowMeter};
+use crate::session_risk::{SessionTracker, SESSION_HEADER, session_id_from_messages};
+use crate::turn_signing::{Conversation, TurnSigner, SIGNATURE_HEADER, SIGNATURES_REQUEST_HEADER, FORGED_TURNS_HEADER};
+use crate::pii_vault::{Pseudonymizer, Vault, VAULT_HEADER};
+use crate::profiles::{client_id, Profile, Profiles};
+use crate::prompt_leak::LeakCheck;
+use crate::redaction_style::RedactionSession;
+use crate::vision_guard::VisionGuard;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub ollama_url: String,
-    pub validation_mode: ValidationMode,
-    pub sensitivity: Sensitivity,
-    pub guard_model: String,
+    pub classifier: Arc<dyn Classifier>,
+    pub sessions: Option<Arc<SessionTracker>>,
+    pub turn_signer: Option<Arc<TurnSigner>>,
+    pub vision_guard: Option<Arc<VisionGuard>>,
+    pub profiles: Arc<Profiles>,
+    pub shadow: Arc<ShadowMeter>,
     pub http_client: reqwest::Client,
 }
 
+impl AppState {
+    pub fn new(ollama_url: &str, classifier: Arc<dyn Classifier>, http_client: reqwest::Client) -> Self {
+        Self {
+            ollama_url: ollama_url.to_string(),
+            classifier,
+            sessions: None,
+            turn_signer: None,
+            vision_guard: None,
+            profiles: Arc::new(Profiles::default()),
+            shadow: Arc::new(ShadowMeter::new()),
+            http_client,
+        }
+    }
+}
+
 pub fn create_app(state: AppState) -> Router {
     Router::new()
-        .route("/", get(|| async { "Molt-Guard Secure Proxy" }))
-        .route("/health", get(|| async { "OK" }))
-        .route("/v1/models", get(list_models_handler))
         .route("/v1/chat/completions", post(chat_completions_handler))
         .route("/api/chat", post(ollama_chat_handler))
         .route("/api/generate", post(ollama_generate_handler))
+        // Only the guarded endpoints above can be explained.
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), explain_requests))
+        // Large enough for the images the profiles accept; the image policy
+        // enforces the actual limits.
+        .route_layer(DefaultBodyLimit::max(state.profiles.max_body_bytes()))
+        .route("/", get(|| async { "Molt-Guard Secure Proxy" }))
+        .route("/health", get(|| async { "OK" }))
+        .route("/v1/models", get(list_models_handler))
+        .route("/molt-guard/shadow", get(shadow_report_handler))
         .fallback(proxy_fallback_handler)
         .with_state(state)
 }
@@ -47,6 +105,8 @@ struct OllamaChatRequest {
     messages: Vec<Message>,
     #[serde(default)]
     stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
 }
 
 #[derive(Deserialize)]
@@ -62,11 +122,8 @@ struct OllamaGenerateRequest {
     prompt: String,
     #[serde(default)]
     stream: bool,
-}
-
-#[derive(Deserialize)]
-struct OllamaGenerateResponse {
-    response: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    images: Option<Vec<String>>,
 }
 
 async fn proxy_fallback_handler(
@@ -150,67 +207,153 @@ async fn list_models_handler(
     }))
 }
 
+/// Sets up an explanation for requests that ask for one, if their profile
+/// allows it, and returns it in `EXPLAIN_HEADER`.
+async fn explain_requests(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let dry_run = explain::requested(request.headers(), DRY_RUN_HEADER);
+    if !dry_run && !explain::requested(request.headers(), EXPLAIN_HEADER) {
+        return next.run(request).await;
+    }
+    let profile = state.profiles.for_request(request.headers());
+    if !profile.explain {
+        println!("!!! EXPLAIN: profile {} may not ask for explanations, header ignored", profile.name);
+        if dry_run {
+            let error_msg = "Dry runs are not enabled for this API key.";
+            let body = match request.uri().path().starts_with("/v1/") {
+                true => serde_json::json!({
+                    "error": { "message": error_msg, "type": "permission_error", "param": null, "code": "dry_run_not_permitted" }
+                }),
+                false => serde_json::json!({ "error": error_msg }),
+            };
+            return (StatusCode::FORBIDDEN, Json(body)).into_response();
+        }
+        return next.run(request).await;
+    }
+
+    let explanation = Arc::new(Explanation::new(&profile.name, profile.shadow, dry_run));
+    request.extensions_mut().insert(explanation.clone());
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(EXPLAIN_HEADER, explanation.header_value());
+    response
+}
+
+/// Shadow mode counts of every profile. They name every profile and what
+/// its traffic would have hit, so only `admin` profiles may read them.
+async fn shadow_report_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let profile = state.profiles.for_request(&headers);
+    if !profile.admin {
+        println!("!!! SHADOW: profile {} may not read the shadow report", profile.name);
+        let body = serde_json::json!({ "error": "The shadow report is not enabled for this API key." });
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+    Json(state.shadow.snapshot()).into_response()
+}
+
 async fn chat_completions_handler(
     State(state): State<AppState>,
-    Json(payload): Json<ChatCompletionRequest>,
-) -> Result<Json<ChatCompletionResponse>, (StatusCode, String)> {
+    headers: HeaderMap,
+    explain: Option<Extension<Arc<Explanation>>>,
+    Json(mut payload): Json<ChatCompletionRequest>,
+) -> Result<Response, (StatusCode, String)> {
     
-    let prompt_guard = PromptGuardClient::new(&state.ollama_url, state.validation_mode, state.sensitivity, &state.guard_model, state.http_client.clone());
-    let middleware = InputValidationMiddleware::new(prompt_guard);
-
-    if let Some(last_message) = payload.messages.last() {
-        if last_message.role == "user" {
-            if let Err(e) = middleware.process(&last_message.content).await {
-                let error_msg = e.to_string();
-                let status_msg = if error_msg.contains("provisioned") {
-                    format!("⏳ **Molt-Guard Security Status**: {}", error_msg)
-                } else {
-                    format!("🛡️ **Molt-Guard Security Alert**: {}", error_msg)
-                };
-
-                return Ok(Json(ChatCompletionResponse {
-                    id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
-                    object: "chat.completion".to_string(),
-                    created: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                    model: payload.model,
-                    choices: vec![Choice {
-                        index: 0,
-                        message: Message {
-                            role: "assistant".to_string(),
-                            content: status_msg,
-                        },
-                        finish_reason: Some("stop".to_string()),
-                    }],
-                    usage: None,
-                    system_fingerprint: None,
-                }));
-            }
-        }
+    let profile = state.profiles.for_request(&headers);
+    let audit = audit(&state, &profile, explain.as_deref().map(Arc::as_ref));
+    let (forged_turns, conversation) = match verify_assistant_turns(&state, &headers, &profile, audit, &mut payload.messages) {
+        Ok(verified) => verified,
+        Err(e) => return Ok(refuse_chat_completion(&profile, payload.model, BlockKind::ForgedTurn, &e.to_string())),
+    };
+    if let Err(e) = filter_input_messages(&profile, audit, &mut payload.messages) {
+        return Ok(refuse_chat_completion(&profile, payload.model, BlockKind::Policy, &e.to_string()));
     }
 
+    let middleware = guard_middleware(&state, audit);
+    let session_id = session_id(&profile, &headers, &payload.messages);
+
+    if let Err(e) = guard_messages(&state, audit, &middleware, session_id.as_deref(), &mut payload.messages).await {
+        let e = e.to_string();
+        return Ok(refuse_chat_completion(&profile, payload.model, BlockKind::of_guard_error(&e), &e));
+    }
+
+    let vault = pseudonymize_messages(&profile, audit, &headers, &mut payload.messages);
+    let leak_check = prepare_leak_check(&profile, audit, &mut payload.messages);
     let url = format!("{}/api/chat", state.ollama_url);
 
+    for message in payload.messages.iter_mut() {
+        if let Some(calls) = &mut message.tool_calls {
+            *calls = calls.iter().map(ToolCall::to_ollama).collect();
+        }
+    }
+    let mut tools = chosen_tools(payload.tools.take(), payload.tool_choice.as_ref());
+    shadow::rewrite(audit, "tool_offer", &mut tools, |tools| *tools = offered_tools(&profile, tools.take()));
     let ollama_request = OllamaChatRequest {
         model: payload.model.clone(),
         messages: payload.messages,
         stream: false,
+        tools: tools.clone(),
     };
+    if let Some(response) = dry_run_response(audit) {
+        return Ok(response);
+    }
 
-    let response = state.http_client.post(&url)
-        .json(&ollama_request)
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let response = match state.http_client.post(&url).json(&ollama_request).send().await {
+        Ok(response) => response,
+        Err(e) => return Ok(upstream_error(&profile, None, e.to_string())),
+    };
 
     if !response.status().is_success() {
+        let status = response.status().as_u16();
         let err_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Ollama error: {}", err_body)));
+        return Ok(upstream_error(&profile, Some(status), format!("Ollama error: {}", err_body)));
     }
 
-    let ollama_response: OllamaChatResponse = response.json().await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut ollama_response: OllamaChatResponse = match response.json().await {
+        Ok(response) => response,
+        Err(e) => return Ok(upstream_error(&profile, None, e.to_string())),
+    };
 
-    let content = apply_filters(ollama_response.message.content);
+    let mut tool_calls = ollama_response.message.tool_calls.take().filter(|calls| !calls.is_empty());
+    let original_calls = tool_calls.clone();
+    let leaked = check_prompt_leak(&profile, audit, leak_check.as_ref(), &ollama_response.message.content);
+    let leaked = leaked.and_then(|()| shadow::apply(audit, "tool_policy", &mut tool_calls, |calls| {
+        check_tool_calls(&profile, calls.as_deref_mut(), tools.as_deref().unwrap_or_default())
+    }));
+    let mut text = ollama_response.message.content.clone();
+    // One session for the whole reply, so typed tokens are numbered across
+    // the text and every argument value.
+    let mut session = profile.filters.session();
+    let filtered = filter_output(&profile, audit, &mut session, &mut text)
+        .and_then(|()| filter_tool_arguments(&profile, audit, &mut session, &mut tool_calls));
+    let withheld = leaked.and(filtered).err().map(|e| e.to_string());
+    let altered = withheld.is_some() || text != ollama_response.message.content || tool_calls != original_calls;
+    let content = match (&withheld, &vault) {
+        // In `error` mode a withheld reply is empty, as from OpenAI's own filter.
+        (Some(_), _) if profile.block_response.mode == BlockMode::Error => String::new(),
+        (Some(reason), _) => profile.block_response.message(reason),
+        (None, Some(vault)) => vault.restore(&text),
+        (None, None) => text,
+    };
+    let tool_calls = match (withheld.is_none(), tool_calls) {
+        (true, Some(calls)) => Some(calls.iter()
+            .map(|call| match &vault {
+                Some(vault) => tool_policy::rewrite_arguments(call, |value| vault.restore(value)).to_openai(),
+                None => call.to_openai(),
+            })
+            .collect::<Vec<_>>()),
+        _ => None,
+    };
+    let finish_reason = match (altered, &tool_calls) {
+        (true, _) => "content_filter",
+        (false, Some(_)) => "tool_calls",
+        (false, None) => "stop",
+    };
+    let mut message = Message {
+        role: ollama_response.message.role,
+        content,
+        tool_calls,
+        ..Message::default()
+    };
+    let signature = state.turn_signer.as_ref().zip(conversation.as_ref()).map(|(signer, conversation)| signer.sign(conversation, &message));
+    message.signature = signature.clone();
 
     let usage = match (ollama_response.prompt_eval_count, ollama_response.eval_count) {
         (Some(p), Some(e)) => Some(crate::api_types::Usage {
@@ -221,107 +364,145 @@ async fn chat_completions_handler(
         _ => None,
     };
 
-    Ok(Json(ChatCompletionResponse {
+    let mut response = Json(ChatCompletionResponse {
         id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
         object: "chat.completion".to_string(),
         created: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
         model: payload.model,
         choices: vec![Choice {
             index: 0,
-            message: Message {
-                role: ollama_response.message.role,
-                content,
-            },
-            finish_reason: Some("stop".to_string()),
+            message,
+            finish_reason: Some(finish_reason.to_string()),
         }],
         usage,
         system_fingerprint: None,
-    }))
+    }).into_response();
+
+    if let Some(signature) = signature
+        && let Ok(value) = signature.parse() {
+        response.headers_mut().insert(SIGNATURE_HEADER, value);
+    }
+    if let Some(id) = vault.as_ref().and_then(Vault::id)
+        && let Ok(value) = id.parse() {
+        response.headers_mut().insert(VAULT_HEADER, value);
+    }
+    if forged_turns > 0 {
+        response.headers_mut().insert(FORGED_TURNS_HEADER, forged_turns.into());
+    }
+    Ok(response)
 }
 
 async fn ollama_chat_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(payload): Json<OllamaChatRequest>,
+    explain: Option<Extension<Arc<Explanation>>>,
+    Json(mut payload): Json<OllamaChatRequest>,
 ) -> Result<Response, (StatusCode, String)> {
     
-    let prompt_guard = PromptGuardClient::new(&state.ollama_url, state.validation_mode, state.sensitivity, &state.guard_model, state.http_client.clone());
-    let middleware = InputValidationMiddleware::new(prompt_guard);
-
-    if let Some(last_message) = payload.messages.last() {
-        if last_message.role == "user" {
-            if let Err(e) = middleware.process(&last_message.content).await {
-                let error_msg = e.to_string();
-                let status_msg = if error_msg.contains("provisioned") {
-                    format!("⏳ **Molt-Guard Security Status**: {}", error_msg)
-                } else {
-                    format!("🛡️ **Molt-Guard Security Alert**: {}", error_msg)
-                };
-
-                let ollama_resp = serde_json::json!({
-                    "model": payload.model,
-                    "created_at": "2026-02-09T00:00:00Z",
-                    "message": {
-                        "role": "assistant",
-                        "content": status_msg
-                    },
-                    "done": true
-                });
-                return Ok(Json(ollama_resp).into_response());
-            }
-        }
+    let profile = state.profiles.for_request(&headers);
+    let audit = audit(&state, &profile, explain.as_deref().map(Arc::as_ref));
+    let (forged_turns, conversation) = match verify_assistant_turns(&state, &headers, &profile, audit, &mut payload.messages) {
+        Ok(verified) => verified,
+        Err(e) => return Ok(refuse_ollama(&profile, "/api/chat", &payload.model, BlockKind::ForgedTurn, &e.to_string())),
+    };
+    if let Err(e) = filter_input_messages(&profile, audit, &mut payload.messages) {
+        return Ok(refuse_ollama(&profile, "/api/chat", &payload.model, BlockKind::Policy, &e.to_string()));
+    }
+
+    let middleware = guard_middleware(&state, audit);
+    let session_id = session_id(&profile, &headers, &payload.messages);
+
+    if let Err(e) = guard_messages(&state, audit, &middleware, session_id.as_deref(), &mut payload.messages).await {
+        let e = e.to_string();
+        return Ok(refuse_ollama(&profile, "/api/chat", &payload.model, BlockKind::of_guard_error(&e), &e));
     }
 
-    proxy_forward_json(&state.http_client, &state.ollama_url, "/api/chat", Method::POST, headers, &payload).await
+    let vault = pseudonymize_messages(&profile, audit, &headers, &mut payload.messages);
+    let leak_check = prepare_leak_check(&profile, audit, &mut payload.messages);
+    shadow::rewrite(audit, "tool_offer", &mut payload.tools, |tools| *tools = offered_tools(&profile, tools.take()));
+    let offered = payload.tools.clone().unwrap_or_default();
+    if let Some(response) = dry_run_response(audit) {
+        return Ok(response);
+    }
+    let rewrite = ResponseRewrite { profile: &profile, audit, vault: vault.as_ref(), leak_check: leak_check.as_ref(), tools: &offered, conversation: conversation.as_ref() };
+    let mut response = proxy_forward_json(&state, "/api/chat", Method::POST, headers, &payload, rewrite).await?;
+    if forged_turns > 0 {
+        response.headers_mut().insert(FORGED_TURNS_HEADER, forged_turns.into());
+    }
+    Ok(response)
 }
 
 async fn ollama_generate_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(payload): Json<OllamaGenerateRequest>,
+    explain: Option<Extension<Arc<Explanation>>>,
+    Json(mut payload): Json<OllamaGenerateRequest>,
 ) -> Result<Response, (StatusCode, String)> {
     
-    let prompt_guard = PromptGuardClient::new(&state.ollama_url, state.validation_mode, state.sensitivity, &state.guard_model, state.http_client.clone());
-    let middleware = InputValidationMiddleware::new(prompt_guard);
-
-    if let Err(e) = middleware.process(&payload.prompt).await {
-        let error_msg = e.to_string();
-        let status_msg = if error_msg.contains("provisioned") {
-            format!("⏳ **Molt-Guard Security Status**: {}", error_msg)
-        } else {
-            format!("🛡️ **Molt-Guard Security Alert**: {}", error_msg)
-        };
+    let profile = state.profiles.for_request(&headers);
+    let audit = audit(&state, &profile, explain.as_deref().map(Arc::as_ref));
+    let middleware = guard_middleware(&state, audit);
+    let session_id = session_id(&profile, &headers, &[]);
 
-        let ollama_resp = serde_json::json!({
-            "model": payload.model,
-            "created_at": "2026-02-09T00:00:00Z",
-            "response": status_msg,
-            "done": true
-        });
-        return Ok(Json(ollama_resp).into_response());
+    if let Err((kind, e)) = check_generate(&state, &profile, audit, &middleware, session_id.as_deref(), &mut payload).await {
+        return Ok(refuse_ollama(&profile, "/api/generate", &payload.model, kind, &e.to_string()));
     }
 
-    proxy_forward_json(&state.http_client, &state.ollama_url, "/api/generate", Method::POST, headers, &payload).await
+    let mut vault = None;
+    shadow::rewrite(audit, "pseudonymize", &mut payload.prompt, |prompt| {
+        vault = with_vault(&profile, &headers, |p, vault| *prompt = p.pseudonymize(vault, prompt));
+    });
+    let vault = vault.filter(|_| audit.enforce);
+    if let Some(response) = dry_run_response(audit) {
+        return Ok(response);
+    }
+    let rewrite = ResponseRewrite { profile: &profile, audit, vault: vault.as_ref(), leak_check: None, tools: &[], conversation: None };
+    proxy_forward_json(&state, "/api/generate", Method::POST, headers, &payload, rewrite).await
+}
+
+/// What happens to a backend response body before it reaches the client.
+struct ResponseRewrite<'a> {
+    /// Output filters to redact with.
+    profile: &'a Profile,
+    /// How the checks of the body run and are reported.
+    audit: Audit<'a>,
+    /// Pseudonyms to restore after redaction.
+    vault: Option<&'a Vault>,
+    /// System prompt the reply must not repeat (`/api/chat` only).
+    leak_check: Option<&'a LeakCheck>,
+    /// Tools the request offered, to check the reply's calls against.
+    tools: &'a [Tool],
+    /// The conversation to sign the assistant message of an `/api/chat`
+    /// reply against, if signing is on.
+    conversation: Option<&'a Conversation>,
 }
 
-async fn proxy_forward_json<T: Serialize>(client: &reqwest::Client, base_url: &str, path: &str, method: Method, headers: HeaderMap, payload: &T) -> Result<Response, (StatusCode, String)> {
-    let url = format!("{}{}", base_url, path);
+/// Forwards `payload` to the backend and redacts the response body with the
+/// profile's filters, then restores pseudonymized values. With a `conversation`
+/// and signing enabled, the assistant message of an `/api/chat` reply (streamed
+/// or not) is signed and the signature returned in `SIGNATURE_HEADER`.
+async fn proxy_forward_json<T: Serialize>(state: &AppState, path: &str, method: Method, headers: HeaderMap, payload: &T, rewrite: ResponseRewrite<'_>) -> Result<Response, (StatusCode, String)> {
+    let url = format!("{}{}", state.ollama_url, path);
     
-    let mut rb = client.request(method, &url);
+    let mut rb = state.http_client.request(method, &url);
     for (key, value) in headers.iter() {
         if key != "host" && key != "content-length" {
             rb = rb.header(key, value);
         }
     }
 
-    let res = rb.json(payload)
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let res = match rb.json(payload).send().await {
+        Ok(res) => res,
+        Err(e) if rewrite.profile.block_response.mode == BlockMode::Error => {
+            return Ok((StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "error": e.to_string() }))).into_response());
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
 
     let mut builder = Response::builder().status(res.status());
     for (key, value) in res.headers().iter() {
-        if key != "transfer-encoding" {
+        // The body is rewritten, so its original length no longer applies.
+        if key != "transfer-encoding" && key != "content-length" {
             builder = builder.header(key, value);
         }
     }
@@ -329,15 +510,443 @@ async fn proxy_forward_json<T: Serialize>(client: &reqwest::Client, base_url: &s
     let res_bytes = res.bytes().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     
     let body_str = String::from_utf8_lossy(&res_bytes);
-    let redacted_str = apply_filters(body_str.to_string());
+    let (profile, audit) = (rewrite.profile, rewrite.audit);
+    let leaked = match rewrite.leak_check {
+        Some(check) => check_prompt_leak(profile, audit, Some(check), &ollama_chat_message(&body_str).content),
+        None => Ok(()),
+    };
+    let mut body = body_str.to_string();
+    let checked = leaked.and_then(|()| shadow::apply(audit, "tool_policy", &mut body, |body| {
+        if profile.tool_policy.is_some() {
+            *body = rewrite_ollama_tool_calls(body, |calls| check_tool_calls(profile, calls.as_deref_mut(), rewrite.tools))?;
+        }
+        Ok(())
+    }));
+    // Arguments are filtered as values first: an argument string is not
+    // code in a reply, so the text filters alone would not see a command.
+    let mut session = profile.filters.session();
+    let checked = checked
+        .and_then(|()| {
+            body = rewrite_ollama_tool_calls(&body, |calls| filter_tool_arguments(profile, audit, &mut session, calls))?;
+            Ok(())
+        })
+        .and_then(|()| filter_output(profile, audit, &mut session, &mut body));
+    let redacted_str = match (checked, rewrite.vault) {
+        (Err(e), _) => blocked_ollama_body(path, &body_str, &profile.block_response.message(&e.to_string())),
+        (Ok(()), Some(vault)) => vault.restore_in_json(&body),
+        (Ok(()), None) => body,
+    };
+
+    if let (Some(signer), Some(conversation)) = (&state.turn_signer, rewrite.conversation) {
+        builder = builder.header(SIGNATURE_HEADER, signer.sign(conversation, &ollama_chat_message(&redacted_str)));
+    }
+    if let Some(id) = rewrite.vault.and_then(Vault::id) {
+        builder = builder.header(VAULT_HEADER, id);
+    }
     
     Ok(builder.body(axum::body::Body::from(redacted_str)).unwrap())
 }
 
-fn apply_filters(content: String) -> String {
-    let secrets_filter = SecretsFilter::new();
-    let pii_filter = PiiFilter::new();
-    let mut filtered = secrets_filter.redact(&content);
-    filtered = pii_filter.redact(&filtered);
-    filtered
+/// Reason given when an output filter finding withholds a reply: a custom
+/// detector, or the `commands` or `packages` filter, with the `block` action.
+const BLOCKED_RESPONSE: &str = "Security block: the response contained restricted content and was withheld.";
+
+/// Replacement for a whole backend body that was refused, in the shape of
+/// the endpoint's (non-streamed) reply.
+fn blocked_ollama_body(path: &str, body: &str, content: &str) -> String {
+    let model = body.lines()
+        .find_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .and_then(|v| v["model"].as_str().map(|m| m.to_string()))
+        .unwrap_or_default();
+    let blocked = match path {
+        "/api/chat" => blocked_ollama_chat(&model, content),
+        _ => blocked_ollama_generate(&model, content),
+    };
+    blocked.to_string()
+}
+
+/// Reply to a request refused before reaching the backend, as the
+/// profile's `block_response` asks.
+fn refuse_chat_completion(profile: &Profile, model: String, kind: BlockKind, error_msg: &str) -> Response {
+    match profile.block_response.mode {
+        BlockMode::Message => Json(blocked_chat_completion(model, &profile.block_response.message(error_msg))).into_response(),
+        BlockMode::Error => (kind.status(), Json(kind.openai_error(error_msg))).into_response(),
+    }
+}
+
+/// `refuse_chat_completion` for the Ollama endpoints, whose errors are
+/// `{"error": "..."}`.
+fn refuse_ollama(profile: &Profile, path: &str, model: &str, kind: BlockKind, error_msg: &str) -> Response {
+    match profile.block_response.mode {
+        BlockMode::Message => {
+            let content = profile.block_response.message(error_msg);
+            let blocked = match path {
+                "/api/chat" => blocked_ollama_chat(model, &content),
+                _ => blocked_ollama_generate(model, &content),
+            };
+            Json(blocked).into_response()
+        }
+        BlockMode::Error => (kind.status(), Json(serde_json::json!({ "error": error_msg }))).into_response(),
+    }
+}
+
+/// A failed call to the backend: a plain 500 in `message` mode, an OpenAI
+/// error in `error` mode.
+fn upstream_error(profile: &Profile, status: Option<u16>, error_msg: String) -> Response {
+    if profile.block_response.mode == BlockMode::Message {
+        return (StatusCode::INTERNAL_SERVER_ERROR, error_msg).into_response();
+    }
+    let (status, kind, code) = match status {
+        Some(429) => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", "rate_limit_exceeded"),
+        Some(404) => (StatusCode::NOT_FOUND, "invalid_request_error", "model_not_found"),
+        Some(400..=499) => (StatusCode::BAD_REQUEST, "invalid_request_error", "upstream_rejected"),
+        _ => (StatusCode::SERVICE_UNAVAILABLE, "server_error", "upstream_unavailable"),
+    };
+    let error = serde_json::json!({ "error": { "message": error_msg, "type": kind, "param": null, "code": code } });
+    (status, Json(error)).into_response()
+}
+
+fn blocked_chat_completion(model: String, content: &str) -> ChatCompletionResponse {
+    ChatCompletionResponse {
+        id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+        object: "chat.completion".to_string(),
+        created: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+        model,
+        choices: vec![Choice {
+            index: 0,
+            message: Message {
+                role: "assistant".to_string(),
+                content: content.to_string(),
+                ..Message::default()
+            },
+            finish_reason: Some("content_filter".to_string()),
+        }],
+        usage: None,
+        system_fingerprint: None,
+    }
+}
+
+fn blocked_ollama_chat(model: &str, content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "model": model,
+        "created_at": "2026-02-09T00:00:00Z",
+        "message": {
+            "role": "assistant",
+            "content": content
+        },
+        "done": true
+    })
+}
+
+fn blocked_ollama_generate(model: &str, content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "model": model,
+        "created_at": "2026-02-09T00:00:00Z",
+        "response": content,
+        "done": true
+    })
+}
+
+/// How the request's policy stages run: enforced unless the profile is in
+/// shadow mode or the client asked for a dry run, and explained if asked.
+fn audit<'a>(state: &'a AppState, profile: &'a Profile, explain: Option<&'a Explanation>) -> Audit<'a> {
+    let dry_run = explain.is_some_and(|e| e.dry_run);
+    Audit {
+        profile: &profile.name,
+        enforce: !profile.shadow && !dry_run,
+        meter: (profile.shadow && !dry_run).then_some(state.shadow.as_ref()),
+        explain,
+    }
+}
+
+/// The guard, tracking session risk except for dry runs.
+fn guard_middleware(state: &AppState, audit: Audit<'_>) -> InputValidationMiddleware<Arc<dyn Classifier>> {
+    let dry_run = audit.explain.is_some_and(|e| e.dry_run);
+    InputValidationMiddleware::new(state.classifier.clone())
+        .with_session_tracker(state.sessions.clone().filter(|_| !dry_run))
+}
+
+/// For a dry run, the explanation to return instead of forwarding.
+fn dry_run_response(audit: Audit<'_>) -> Option<Response> {
+    let explain = audit.explain.filter(|e| e.dry_run)?;
+    println!("!!! EXPLAIN: dry run for profile {}, not forwarded", audit.profile);
+    Some(Json(explain.to_json()).into_response())
+}
+
+/// Applies the forged-turn policy when signing is enabled, then strips the
+/// signatures so the backend never sees them. Returns how many turns were
+/// forged and, with signing, the conversation to sign the reply against.
+fn verify_assistant_turns(state: &AppState, headers: &HeaderMap, profile: &Profile, audit: Audit<'_>, messages: &mut Vec<Message>) -> anyhow::Result<(usize, Option<Conversation>)> {
+    let mut forged = 0;
+    let mut conversation = None;
+    if let Some(signer) = &state.turn_signer {
+        let client = client_id(profile, headers);
+        let header = headers.get(SIGNATURES_REQUEST_HEADER).and_then(|v| v.to_str().ok());
+        // The reply follows the history as the client sent it, before any
+        // turn is dropped or rewritten.
+        conversation = Some(Conversation::of(&client, messages));
+        shadow::apply(audit, "forged_turns", messages, |messages| {
+            forged = signer.enforce(&client, messages, header)?;
+            Ok(())
+        })?;
+        if let Some(explain) = audit.explain {
+            explain.detail("forged_turns", "forged", forged);
+        }
+    }
+    for message in messages.iter_mut() {
+        message.signature = None;
+    }
+    Ok((forged, conversation))
+}
+
+/// Applies the profile's input filters and image policy to every message.
+/// Runs before the guard so that a remote classifier never receives what
+/// they remove.
+fn filter_input_messages(profile: &Profile, audit: Audit<'_>, messages: &mut Vec<Message>) -> anyhow::Result<()> {
+    if let Some(dlp) = &profile.input_dlp {
+        if let Some(explain) = audit.explain {
+            let findings: Vec<_> = messages.iter()
+                .enumerate()
+                .flat_map(|(i, m)| dlp.scan(&m.content).into_iter().map(move |f| serde_json::json!({ "message": i, "finding": f })))
+                .collect();
+            explain.detail("input_filters", "findings", findings);
+        }
+        shadow::apply(audit, "input_filters", messages, |messages| dlp.apply_messages(&profile.name, messages))?;
+    }
+    shadow::apply(audit, "images", messages, |messages| profile.image_policy.apply_messages(&profile.name, messages))
+}
+
+/// Runs the guard over one prompt, explaining its score.
+async fn guard_prompt(audit: Audit<'_>, middleware: &InputValidationMiddleware<Arc<dyn Classifier>>, session_id: Option<&str>, prompt: &str) -> anyhow::Result<()> {
+    let assessment = middleware.assess_turn(session_id, prompt).await;
+    if let (Some(explain), Ok(assessment)) = (audit.explain, &assessment) {
+        explain.detail("guard", "score", (assessment.verdict.score as f64 * 100.0).round() / 100.0);
+        if !assessment.verdict.reason.is_empty() {
+            explain.detail("guard", "rule", &assessment.verdict.reason);
+        }
+        if let Some(risk) = assessment.session_risk {
+            explain.detail("guard", "session_risk", (risk as f64 * 100.0).round() / 100.0);
+        }
+    }
+    shadow::verdict(audit, "guard", assessment.and_then(|a| a.result()))
+}
+
+/// Runs the guard over the last user message and, if enabled, the vision
+/// guard over the attached images.
+async fn guard_messages(state: &AppState, audit: Audit<'_>, middleware: &InputValidationMiddleware<Arc<dyn Classifier>>, session_id: Option<&str>, messages: &mut Vec<Message>) -> anyhow::Result<()> {
+    if let Some(last_message) = messages.last()
+        && last_message.role == "user" {
+        guard_prompt(audit, middleware, session_id, &last_message.content).await?;
+    }
+
+    if let Some(guard) = &state.vision_guard
+        && messages.iter().any(|m| m.images.is_some()) {
+        let mut safe = messages.clone();
+        let result = guard.check_messages(&mut safe).await.map(|_| ());
+        shadow::apply(audit, "vision_guard", messages, |messages| result.map(|()| *messages = safe))?;
+    }
+    Ok(())
+}
+
+/// Runs the input filters, image policy, guard and vision guard over an
+/// `/api/generate` request, as `filter_input_messages` and `guard_messages`
+/// do for chat requests.
+async fn check_generate(state: &AppState, profile: &Profile, audit: Audit<'_>, middleware: &InputValidationMiddleware<Arc<dyn Classifier>>, session_id: Option<&str>, payload: &mut OllamaGenerateRequest) -> Result<(), (BlockKind, anyhow::Error)> {
+    if let Some(dlp) = &profile.input_dlp {
+        if let Some(explain) = audit.explain {
+            explain.detail("input_filters", "findings", dlp.scan(&payload.prompt));
+        }
+        shadow::apply(audit, "input_filters", &mut payload.prompt, |prompt| {
+            *prompt = dlp.apply(&profile.name, prompt)?;
+            Ok(())
+        }).map_err(|e| (BlockKind::Policy, e))?;
+    }
+    shadow::apply(audit, "images", &mut payload.images, |images| match images {
+        Some(images) => profile.image_policy.apply(&profile.name, images),
+        None => Ok(()),
+    }).map_err(|e| (BlockKind::Policy, e))?;
+
+    let guard_error = |e: anyhow::Error| (BlockKind::of_guard_error(&e.to_string()), e);
+    guard_prompt(audit, middleware, session_id, &payload.prompt).await.map_err(guard_error)?;
+
+    if let Some(guard) = &state.vision_guard
+        && let Some(images) = &payload.images {
+        let mut safe = images.clone();
+        let result = guard.check_images(&mut safe).await.map(|_| ());
+        shadow::apply(audit, "vision_guard", &mut payload.images, |images| result.map(|()| *images = Some(safe)))
+            .map_err(guard_error)?;
+    }
+    Ok(())
+}
+
+/// Pseudonymizes the messages if the profile asks for it, returning the
+/// vault to restore the reply with. When not enforcing, the messages are
+/// left alone and there is nothing to restore.
+fn pseudonymize_messages(profile: &Profile, audit: Audit<'_>, headers: &HeaderMap, messages: &mut Vec<Message>) -> Option<Vault> {
+    let mut vault = None;
+    shadow::rewrite(audit, "pseudonymize", messages, |messages| {
+        vault = with_vault(profile, headers, |p, vault| p.pseudonymize_messages(vault, messages));
+    });
+    vault.filter(|_| audit.enforce)
+}
+
+/// Builds the prompt leak check. When not enforcing, the canary goes into a
+/// copy of the messages, so only the overlap check can fire.
+fn prepare_leak_check(profile: &Profile, audit: Audit<'_>, messages: &mut [Message]) -> Option<LeakCheck> {
+    let guard = profile.prompt_leak.as_ref()?;
+    match audit.enforce {
+        true => guard.prepare(messages),
+        false => guard.prepare(&mut messages.to_vec()),
+    }
+}
+
+/// Compares the raw model reply with the request's system prompt. Runs
+/// before redaction, which would break up verbatim copies.
+fn check_prompt_leak(profile: &Profile, audit: Audit<'_>, check: Option<&LeakCheck>, reply: &str) -> anyhow::Result<()> {
+    match check {
+        Some(check) => shadow::verdict(audit, "prompt_leak", check.check(&profile.name, reply)),
+        None => Ok(()),
+    }
+}
+
+/// Redacts `text` with the profile's output filters. Errors if a finding
+/// withholds the whole reply.
+fn filter_output(profile: &Profile, audit: Audit<'_>, session: &mut RedactionSession<'_>, text: &mut String) -> anyhow::Result<()> {
+    shadow::apply(audit, "output_filters", text, |text| {
+        let redaction = profile.filters.apply_in(text, session);
+        if let Some(explain) = audit.explain {
+            explain.detail("output_filters", "findings", &redaction.findings);
+        }
+        if redaction.blocked {
+            return Err(anyhow::anyhow!(BLOCKED_RESPONSE));
+        }
+        *text = redaction.text;
+        Ok(())
+    })
+}
+
+/// Redacts the string values of tool call arguments with the profile's
+/// output filters, whether or not a tool policy is configured. Errors if a
+/// finding withholds the whole reply.
+fn filter_tool_arguments(profile: &Profile, audit: Audit<'_>, session: &mut RedactionSession<'_>, calls: &mut Option<Vec<ToolCall>>) -> anyhow::Result<()> {
+    shadow::apply(audit, "output_filters", calls, |calls| {
+        let mut blocked = false;
+        let mut findings = Vec::new();
+        for call in calls.iter_mut().flatten() {
+            *call = tool_policy::rewrite_arguments(call, |value| {
+                let redaction = profile.filters.apply_argument_in(value, session);
+                blocked |= redaction.blocked;
+                findings.extend(redaction.findings);
+                redaction.text
+            });
+        }
+        if let Some(explain) = audit.explain
+            && !findings.is_empty() {
+            explain.detail("output_filters", "tool_call_findings", &findings);
+        }
+        if blocked {
+            return Err(anyhow::anyhow!(BLOCKED_RESPONSE));
+        }
+        Ok(())
+    })
+}
+
+/// Maps OpenAI's `tool_choice` onto the tools sent to Ollama, which has no
+/// such option: `none` offers no tools and a named function only that one.
+/// `required` cannot be enforced and is treated as `auto`.
+fn chosen_tools(tools: Option<Vec<Tool>>, choice: Option<&serde_json::Value>) -> Option<Vec<Tool>> {
+    let tools = tools.filter(|tools| !tools.is_empty())?;
+    match choice {
+        Some(serde_json::Value::String(choice)) if choice == "none" => None,
+        Some(choice) if choice["function"]["name"].is_string() => {
+            let name = choice["function"]["name"].as_str().unwrap_or_default();
+            Some(tools.into_iter().filter(|t| t.function.name == name).collect())
+        }
+        _ => Some(tools),
+    }
+}
+
+/// The request's tools minus those the profile's tool policy withholds.
+fn offered_tools(profile: &Profile, tools: Option<Vec<Tool>>) -> Option<Vec<Tool>> {
+    match &profile.tool_policy {
+        Some(policy) => tools.map(|tools| policy.allowed_tools(&profile.name, tools)),
+        None => tools,
+    }
+}
+
+/// Applies the profile's tool policy to the calls of a reply.
+fn check_tool_calls(profile: &Profile, calls: Option<&mut [ToolCall]>, offered: &[Tool]) -> anyhow::Result<()> {
+    match (&profile.tool_policy, calls) {
+        (Some(policy), Some(calls)) => policy.check_calls(&profile.name, calls, offered),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrites the tool calls of an Ollama `/api/chat` body, streamed or not,
+/// with `rewrite`. Lines whose calls it leaves alone are kept as they were.
+fn rewrite_ollama_tool_calls(body: &str, mut rewrite: impl FnMut(&mut Option<Vec<ToolCall>>) -> anyhow::Result<()>) -> anyhow::Result<String> {
+    if !body.contains("\"tool_calls\"") {
+        return Ok(body.to_string());
+    }
+    let mut lines = Vec::new();
+    for line in body.split('\n') {
+        let mut value = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(value) if value["message"]["tool_calls"].is_array() => value,
+            _ => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        let original: Vec<ToolCall> = serde_json::from_value(value["message"]["tool_calls"].take())?;
+        let mut calls = Some(original.clone());
+        rewrite(&mut calls)?;
+        match calls {
+            Some(calls) if calls != original => {
+                value["message"]["tool_calls"] = serde_json::to_value(calls)?;
+                lines.push(value.to_string());
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Runs `pseudonymize` with the request's vault if the profile pseudonymizes
+/// input, returning the vault needed to restore the reply. Called after the
+/// guard, which sees the original text. Session vaults belong to the client's
+/// API key and are named by the id the proxy issued in `VAULT_HEADER`.
+fn with_vault(profile: &Profile, headers: &HeaderMap, pseudonymize: impl FnOnce(&Pseudonymizer, &mut Vault)) -> Option<Vault> {
+    let pseudonymizer = profile.pseudonymizer.as_ref()?;
+    let id = headers.get(VAULT_HEADER).and_then(|v| v.to_str().ok());
+    Some(pseudonymizer.with_vault(&client_id(profile, headers), id, pseudonymize))
+}
+
+/// Assistant message of an Ollama `/api/chat` body, joining the text and
+/// tool calls of the chunks of a streamed (newline-delimited) reply.
+fn ollama_chat_message(body: &str) -> Message {
+    let chunks: Vec<serde_json::Value> = match serde_json::from_str(body) {
+        Ok(value) => vec![value],
+        Err(_) => body.lines().filter_map(|line| serde_json::from_str(line).ok()).collect(),
+    };
+    let mut message = Message { role: "assistant".to_string(), ..Message::default() };
+    for chunk in chunks {
+        message.content.push_str(chunk["message"]["content"].as_str().unwrap_or_default());
+        if let Ok(calls) = serde_json::from_value::<Vec<ToolCall>>(chunk["message"]["tool_calls"].clone())
+            && !calls.is_empty() {
+            message.tool_calls.get_or_insert_with(Vec::new).extend(calls);
+        }
+    }
+    message
+}
+
+/// Conversation identity for session risk tracking: the client's explicit
+/// session header if present, otherwise a hash of the conversation opening.
+/// Both are scoped by the API key, so one client's risk never counts against
+/// another's conversation.
+fn session_id(profile: &Profile, headers: &HeaderMap, messages: &[Message]) -> Option<String> {
+    let client = client_id(profile, headers);
+    headers.get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| format!("{}/{}", client, v))
+        .or_else(|| session_id_from_messages(&client, messages))
 }
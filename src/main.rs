@@ -1,5 +1,14 @@
-use molt_guard::{create_app, AppState, prompt_guard::{ValidationMode, Sensitivity}, ollama_client::OllamaClient};
+use molt_guard::{create_app, AppState, prompt_guard::{PromptGuardClient, ValidationMode, Sensitivity}, ollama_client::OllamaClient};
+use molt_guard::classifier::{AnyOfClassifier, Classifier, HeuristicClassifier, ModerationClassifier};
+use molt_guard::embedding_guard::{EmbeddingIndex, EmbeddingSimilarityClassifier};
+use molt_guard::local_model::LocalModelClassifier;
+use molt_guard::session_risk::{SessionPolicy, SessionTracker};
+use molt_guard::turn_signing::{ForgedTurnPolicy, TurnSigner};
+use molt_guard::vision_guard::{VisionAction, VisionGuard};
+use molt_guard::profiles::Profiles;
+use std::str::FromStr;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -15,6 +24,7 @@ async fn main() -> anyhow::Result<()> {
     let validation_mode_str = std::env::var("VALIDATION_MODE").unwrap_or_else(|_| "Local".to_string());
     let validation_mode = match validation_mode_str.as_str() {
         "Remote" => ValidationMode::Remote,
+        "Moderation" => ValidationMode::Moderation,
         _ => ValidationMode::Local,
     };
     println!("Validation Mode: {:?}", validation_mode);
@@ -48,14 +58,90 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    let state = AppState {
-        ollama_url,
-        validation_mode,
-        sensitivity,
-        guard_model,
-        http_client,
+    let classifier: Arc<dyn Classifier> = match validation_mode {
+        ValidationMode::Local => match std::env::var("LOCAL_MODEL_PATH") {
+            Ok(model_path) => {
+                let threads = std::env::var("LOCAL_MODEL_THREADS").ok().and_then(|v| v.parse().ok()).unwrap_or(2);
+                let max_batch = std::env::var("LOCAL_MODEL_BATCH_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(8);
+                println!("Loading local classifier model from {} ({} threads, batch size {})", model_path, threads, max_batch);
+                Arc::new(LocalModelClassifier::load(std::path::Path::new(&model_path), sensitivity, threads, max_batch)?)
+            }
+            Err(_) => Arc::new(HeuristicClassifier::new(sensitivity)),
+        },
+        ValidationMode::Remote => Arc::new(PromptGuardClient::new(&ollama_url, sensitivity, &guard_model, http_client.clone())),
+        ValidationMode::Moderation => {
+            let moderation_url = std::env::var("MODERATION_URL").unwrap_or_else(|_| "https://api.openai.com".to_string());
+            let moderation_model = std::env::var("MODERATION_MODEL").unwrap_or_else(|_| "omni-moderation-latest".to_string());
+            let moderation_api_key = std::env::var("MODERATION_API_KEY").ok();
+            println!("Moderation endpoint: {} ({})", moderation_url, moderation_model);
+            Arc::new(ModerationClassifier::new(&moderation_url, &moderation_model, moderation_api_key, sensitivity, http_client.clone()))
+        }
     };
 
+    let classifier: Arc<dyn Classifier> = match std::env::var("EMBEDDING_INDEX_PATH") {
+        Ok(index_path) => {
+            let index = EmbeddingIndex::load(std::path::Path::new(&index_path))?;
+            let threshold = std::env::var("EMBEDDING_SIMILARITY_THRESHOLD").ok().and_then(|v| v.parse().ok()).unwrap_or(0.85);
+            println!("Embedding index: {} ({} entries, model {}, threshold {})", index_path, index.entries.len(), index.model, threshold);
+            let embedding_classifier = EmbeddingSimilarityClassifier::new(OllamaClient::new_with_client(&ollama_url, http_client.clone()), index, threshold);
+            Arc::new(AnyOfClassifier::new(vec![classifier, Arc::new(embedding_classifier)]))
+        }
+        Err(_) => classifier,
+    };
+
+    let mut state = AppState::new(&ollama_url, classifier, http_client);
+
+    if let Ok(config_path) = std::env::var("MOLT_GUARD_CONFIG") {
+        state.profiles = Arc::new(Profiles::load(std::path::Path::new(&config_path))?);
+        println!("Loaded profiles from {}", config_path);
+
+        // Detector files are re-read on SIGHUP, without a restart.
+        #[cfg(unix)]
+        {
+            let profiles = state.profiles.clone();
+            let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+            tokio::spawn(async move {
+                while hangups.recv().await.is_some() {
+                    match profiles.reload_detectors() {
+                        Ok(()) => println!("Reloaded detector files"),
+                        Err(e) => eprintln!("Warning: Failed to reload detector files: {}", e),
+                    }
+                }
+            });
+        }
+    }
+
+    if std::env::var("SESSION_TRACKING").is_ok_and(|v| v == "true") {
+        let mut policy = SessionPolicy::default();
+        if let Some(block_at) = std::env::var("SESSION_RISK_BLOCK_THRESHOLD").ok().and_then(|v| v.parse().ok()) {
+            policy.block_at = block_at;
+        }
+        println!("Session risk tracking enabled (block threshold {})", policy.block_at);
+        state.sessions = Some(Arc::new(SessionTracker::new(policy)));
+    }
+
+    if let Ok(signing_key) = std::env::var("ASSISTANT_SIGNING_KEY") {
+        let policy_str = std::env::var("FORGED_TURN_POLICY").unwrap_or_else(|_| "Flag".to_string());
+        let policy = ForgedTurnPolicy::from_str(&policy_str).unwrap_or(ForgedTurnPolicy::Flag);
+        println!("Assistant turn signing enabled (forged turn policy: {:?})", policy);
+        state.turn_signer = Some(Arc::new(TurnSigner::new(signing_key.as_bytes(), policy)));
+    }
+
+    if let Ok(vision_model) = std::env::var("VISION_GUARD_MODEL") {
+        let action_str = std::env::var("VISION_GUARD_ACTION").unwrap_or_else(|_| "Block".to_string());
+        let action = VisionAction::from_str(&action_str).unwrap_or(VisionAction::Block);
+        println!("Vision guard enabled: {} (unsafe images: {:?})", vision_model, action);
+
+        let client = OllamaClient::new_with_client(&ollama_url, state.http_client.clone());
+        let model = vision_model.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.ensure_model_exists(&model).await {
+                eprintln!("Warning: Failed to ensure vision guard model '{}' exists: {}", model, e);
+            }
+        });
+        state.vision_guard = Some(Arc::new(VisionGuard::new(&ollama_url, &vision_model, action, state.http_client.clone())));
+    }
+
     // Define the app routes
     let app = create_app(state);
 
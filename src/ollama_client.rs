@@ -22,6 +22,17 @@ struct PullModelRequest {
     stream: bool,
 }
 
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
 impl OllamaClient {
     pub fn new(base_url: &str) -> Self {
         Self {
@@ -67,6 +78,27 @@ impl OllamaClient {
         Ok(())
     }
 
+    /// Embeds each input with `model` via `/api/embed`, preserving order.
+    pub async fn embed(&self, model_name: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embed", self.base_url);
+        let request = EmbedRequest {
+            model: model_name,
+            input: inputs,
+        };
+
+        let response = self.http_client.post(&url).json(&request).send().await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to embed input: {}", response.status()));
+        }
+
+        let body: EmbedResponse = response.json().await?;
+        if body.embeddings.len() != inputs.len() {
+            return Err(anyhow::anyhow!("Expected {} embeddings, got {}", inputs.len(), body.embeddings.len()));
+        }
+        Ok(body.embeddings)
+    }
+
     pub async fn ensure_model_exists(&self, model_name: &str) -> Result<()> {
         if !self.check_model_exists(model_name).await? {
             println!("Model {} missing, pulling...", model_name);
@@ -124,4 +156,20 @@ mod tests {
         let result = client.pull_model("prompt-guard:latest").await;
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_embed() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .and(wiremock::matchers::body_json(json!({"model": "nomic-embed-text", "input": ["a", "b"]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"embeddings": [[1.0, 0.0], [0.0, 1.0]]})))
+            .mount(&mock_server)
+            .await;
+
+        let client = OllamaClient::new(&mock_server.uri());
+        let embeddings = client.embed("nomic-embed-text", &["a".to_string(), "b".to_string()]).await.unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+}
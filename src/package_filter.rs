@@ -0,0 +1,361 @@
+use crate::filters::{Filter, Finding};
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+/// Added to a popular name to make a plausible-looking fake, e.g.
+/// `requests-python` or `node-axios`.
+const AFFIXES: &[&str] = &["python-", "py-", "py", "-python", "-py", "node-", "-node", "js-", "-js", ".js", "-dev", "-lib"];
+
+/// Names shorter than this are too close to too many others to compare by
+/// edit distance.
+const MIN_COMPARED_LEN: usize = 5;
+
+/// What happens to a reply recommending a suspicious package.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageAction {
+    /// Add a warning naming the package it imitates.
+    #[default]
+    Warn,
+    /// Withhold the whole reply.
+    Block,
+}
+
+/// Per-profile settings of the `packages` filter.
+///
+/// ```json
+/// { "packages": { "action": "block" } }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PackagePolicy {
+    pub action: PackageAction,
+}
+
+/// A package registry: how its install commands look, and its most popular
+/// packages from `data/popular_packages`.
+struct Ecosystem {
+    kind: &'static str,
+    /// An install command; the `args` group holds everything after it.
+    command: Regex,
+    /// Flags whose value is the next argument rather than a package.
+    value_flags: &'static [&'static str],
+    /// Popular names, normalized, with their original spelling.
+    popular: Vec<(String, &'static str)>,
+    normalize: fn(&str) -> String,
+    /// Extract the package name from an argument such as `requests>=2.0`.
+    package: fn(&str) -> Option<&str>,
+}
+
+/// Commands end at a shell operator, or at a quote or backslash so that a
+/// match stays inside its string in a raw JSON body.
+const ARGS: &str = r#"(?P<args>[^\n;&|`"\\)]*)"#;
+
+static ECOSYSTEMS: LazyLock<Vec<Ecosystem>> = LazyLock::new(|| {
+    let ecosystem = |kind, command: &str, value_flags, list: &'static str, normalize: fn(&str) -> String, package: fn(&str) -> Option<&str>| Ecosystem {
+        kind,
+        command: Regex::new(&format!("{}{}", command, ARGS)).unwrap(),
+        value_flags,
+        popular: list.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| (normalize(l), l))
+            .collect(),
+        normalize,
+        package,
+    };
+    vec![
+        ecosystem(
+            "pypi_typosquat",
+            r"(?i)\b(?:(?:pip[23]?(?:\.\d+)?|python[23]?(?:\.\d+)?\s+-m\s+pip|uv\s+pip|pipx)\s+install|(?:poetry|uv|pdm|rye)\s+add)\b",
+            &["-r", "--requirement", "-c", "--constraint", "-e", "--editable", "-i", "--index-url", "--extra-index-url", "-t", "--target", "--prefix", "--root", "-f", "--find-links", "--python", "--trusted-host", "--proxy", "--platform", "--python-version"],
+            include_str!("../data/popular_packages/pypi.txt"),
+            // PEP 503: case, `-`, `_` and `.` do not distinguish projects.
+            |name| name.to_lowercase().split(['-', '_', '.']).filter(|p| !p.is_empty()).collect::<Vec<_>>().join("-"),
+            |arg| arg.split(['[', '<', '>', '=', '!', '~', ';', '@', ',', '(']).next(),
+        ),
+        ecosystem(
+            "npm_typosquat",
+            r"\b(?:(?:npm|pnpm|bun)\s+(?:install|i|add)|yarn\s+(?:global\s+)?add)\b",
+            &["--registry", "--tag", "--prefix", "--workspace", "-w"],
+            include_str!("../data/popular_packages/npm.txt"),
+            |name| name.to_lowercase(),
+            // Scoped packages are owned by their scope and not compared.
+            |arg| if arg.starts_with('@') { None } else { arg.split('@').next() },
+        ),
+        ecosystem(
+            "crates_typosquat",
+            r"\bcargo\s+(?:add|install)\b",
+            &["--version", "--vers", "--git", "--path", "--features", "-F", "--branch", "--tag", "--rev", "--registry", "--rename", "--root", "--bin", "--example", "--target", "--package", "-p", "-j", "--jobs", "--index", "--manifest-path", "--profile", "--target-dir"],
+            include_str!("../data/popular_packages/crates.txt"),
+            // crates.io treats `-` and `_` as the same name.
+            |name| name.to_lowercase().replace('_', "-"),
+            |arg| arg.split('@').next(),
+        ),
+        ecosystem(
+            "rubygems_typosquat",
+            r"\b(?:gem\s+install|bundle\s+add)\b",
+            &["-v", "--version", "-s", "--source", "-i", "--install-dir", "--platform", "-n", "--bindir"],
+            include_str!("../data/popular_packages/rubygems.txt"),
+            |name| name.to_lowercase(),
+            |arg| Some(arg),
+        ),
+        ecosystem(
+            "go_typosquat",
+            r"\bgo\s+(?:get|install)\b",
+            &["-ldflags", "-tags", "-gcflags", "-modfile", "-o"],
+            include_str!("../data/popular_packages/go.txt"),
+            |name| name.to_lowercase(),
+            |arg| arg.split('@').next(),
+        ),
+    ]
+});
+
+/// Every kind the filter reports.
+pub fn detector_kinds() -> Vec<&'static str> {
+    ECOSYSTEMS.iter().map(|e| e.kind).collect()
+}
+
+/// Edit distance counting a swap of two adjacent characters as one edit
+/// (optimal string alignment).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d: Vec<Vec<usize>> = (0..=a.len()).map(|i| (0..=b.len()).map(|j| if i == 0 { j } else if j == 0 { i } else { 0 }).collect()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// `name` cut to as many `/`-separated segments as `popular` has, so Go
+/// packages are compared by their module path.
+fn same_depth<'a>(name: &'a str, popular: &str) -> &'a str {
+    let depth = popular.matches('/').count() + 1;
+    match name.match_indices('/').nth(depth - 1) {
+        Some((i, _)) => &name[..i],
+        None => name,
+    }
+}
+
+/// Whether `longer` is `shorter` with a trailing digit or plural `s`, the
+/// way genuine packages such as `markdown2` or `websockets` differ from
+/// popular ones. Doubling an `s`, as in `requestss`, is still a typo.
+fn numbered_or_plural(longer: &str, shorter: &str) -> bool {
+    match longer.strip_prefix(shorter) {
+        Some("s") => !shorter.ends_with('s'),
+        Some(tail) => tail.len() == 1 && tail.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Typosquatting heuristics: the same name with its separators dropped,
+/// a popular name with a decorative prefix or suffix, or a small typo other
+/// than a trailing digit or plural `s`.
+fn resembles(name: &str, popular: &str) -> bool {
+    let bare = |s: &str| s.replace(['-', '_', '.'], "");
+    if bare(name) == bare(popular) {
+        return true;
+    }
+    if !popular.contains('/') && AFFIXES.iter().any(|a| name.strip_prefix(a) == Some(popular) || name.strip_suffix(a) == Some(popular)) {
+        return true;
+    }
+    if numbered_or_plural(name, popular) || numbered_or_plural(popular, name) {
+        return false;
+    }
+    let longest = name.chars().count().max(popular.chars().count());
+    let allowed = if longest < 9 { 1 } else { 2 };
+    name.len() >= MIN_COMPARED_LEN && popular.len() >= MIN_COMPARED_LEN
+        && name.len().abs_diff(popular.len()) <= allowed
+        && edit_distance(name, popular) <= allowed
+}
+
+impl Ecosystem {
+    /// The popular package `name` seems to imitate, or `None` if it is one
+    /// of them (or a subpackage) or looks like none of them.
+    fn imitated(&self, name: &str) -> Option<&'static str> {
+        let name = (self.normalize)(name);
+        if self.popular.iter().any(|(p, _)| same_depth(&name, p) == p) {
+            return None;
+        }
+        self.popular.iter()
+            .find(|(p, _)| resembles(same_depth(&name, p), p))
+            .map(|(_, original)| *original)
+    }
+
+    /// Suspicious packages in the arguments of an install command, each with
+    /// the package it imitates.
+    fn suspects<'a>(&self, args: &'a str) -> Vec<(&'a str, &'static str)> {
+        let mut suspects = Vec::new();
+        let mut skip_next = false;
+        for arg in args.split_whitespace() {
+            if std::mem::take(&mut skip_next) {
+                continue;
+            }
+            if arg.starts_with('-') {
+                skip_next = self.value_flags.contains(&arg);
+                continue;
+            }
+            let arg = arg.trim_matches('\'');
+            // Paths, URLs and VCS references name no registry package.
+            let is_path = arg.starts_with('.') || arg.contains(':') || (arg.contains('/') && self.kind != "go_typosquat");
+            let Some(name) = (self.package)(arg).filter(|_| !is_path) else { continue };
+            let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphanumeric())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || "._-/".contains(c));
+            if let Some(popular) = self.imitated(name).filter(|_| valid) {
+                suspects.push((name, popular));
+            }
+        }
+        suspects
+    }
+}
+
+/// Flags `pip`, `npm`, `cargo`, `gem` and `go` install commands in a reply
+/// that name a package resembling, but not being, a popular one.
+pub struct PackageFilter {
+    action: PackageAction,
+    ecosystems: Vec<&'static Ecosystem>,
+}
+
+impl PackageFilter {
+    /// `disabled` may hold kinds of other filters; they are ignored.
+    pub fn new(policy: &PackagePolicy, disabled: &[String]) -> Self {
+        Self {
+            action: policy.action,
+            ecosystems: ECOSYSTEMS.iter().filter(|e| !disabled.iter().any(|d| d == e.kind)).collect(),
+        }
+    }
+}
+
+impl Filter for PackageFilter {
+    fn name(&self) -> &str {
+        "packages"
+    }
+
+    fn scan(&self, input: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for ecosystem in &self.ecosystems {
+            for caps in ecosystem.command.captures_iter(input) {
+                if ecosystem.suspects(&caps["args"]).is_empty() {
+                    continue;
+                }
+                let command = caps.get(0).unwrap();
+                findings.push(Finding {
+                    filter: "packages".to_string(),
+                    kind: ecosystem.kind.to_string(),
+                    start: command.start(),
+                    end: command.start() + command.as_str().trim_end().len(),
+                    confidence: 0.8,
+                });
+            }
+        }
+        findings
+    }
+
+    fn placeholder(&self, finding: &Finding, value: &str) -> String {
+        let suspects = self.ecosystems.iter()
+            .find(|e| e.kind == finding.kind)
+            .and_then(|e| e.command.captures(value).map(|caps| e.suspects(caps.name("args").map_or("", |a| a.as_str()))))
+            .unwrap_or_default();
+        let warnings: Vec<String> = suspects.iter()
+            .map(|(name, popular)| format!("{} looks like a typosquat of {}", name, popular))
+            .collect();
+        for warning in &warnings {
+            println!("!!! PACKAGE FILTER: {} ({})", warning, finding.kind);
+        }
+        // A trailing comment keeps the command valid shell.
+        format!("{}  # ⚠️ Molt-Guard: {}", value, warnings.join("; "))
+    }
+
+    fn blocks(&self, _finding: &Finding) -> bool {
+        self.action == PackageAction::Block
+    }
+
+    /// Suspect commands are kept with a warning, or the reply withheld.
+    fn redacts(&self, _finding: &Finding) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suspects(input: &str) -> Vec<(String, String)> {
+        let filter = PackageFilter::new(&PackagePolicy::default(), &[]);
+        filter.scan(input).iter()
+            .flat_map(|f| {
+                let ecosystem = filter.ecosystems.iter().find(|e| e.kind == f.kind).unwrap();
+                let caps = ecosystem.command.captures(&input[f.start..f.end]).unwrap();
+                ecosystem.suspects(caps.name("args").unwrap().as_str()).into_iter()
+                    .map(|(n, p)| (n.to_string(), p.to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_near_misses_flagged() {
+        let pair = |n: &str, p: &str| vec![(n.to_string(), p.to_string())];
+        assert_eq!(suspects("pip install requestss"), pair("requestss", "requests"));
+        assert_eq!(suspects("pip3 install -U python-dateutl"), pair("python-dateutl", "python-dateutil"));
+        assert_eq!(suspects("python -m pip install beautifulsop4"), pair("beautifulsop4", "beautifulsoup4"));
+        assert_eq!(suspects("npm install --save expresss"), pair("expresss", "express"));
+        assert_eq!(suspects("yarn add crossenv"), pair("crossenv", "cross-env"));
+        assert_eq!(suspects("npm i node-axios"), pair("node-axios", "axios"));
+        assert_eq!(suspects("cargo add serde_jsno"), pair("serde_jsno", "serde_json"));
+        assert_eq!(suspects("gem install nokogri"), pair("nokogri", "nokogiri"));
+        assert_eq!(suspects("go get github.com/gin-gonlc/gin@latest"), pair("github.com/gin-gonlc/gin", "github.com/gin-gonic/gin"));
+    }
+
+    #[test]
+    fn test_genuine_packages_pass() {
+        for command in [
+            "pip install requests 'numpy>=1.26' PyYAML python_dateutil scapy -r requirements.txt",
+            "pip install -e . git+https://github.com/psf/requests.git",
+            "npm install @types/nodee react react-dom preact --registry https://registry.example.com",
+            "cargo add serde-json tokio@1 --features full",
+            "cargo install --path .",
+            "go get github.com/gin-gonic/gin/binding golang.org/x/text@v0.14.0",
+            "pip install some-internal-tool",
+        ] {
+            assert!(suspects(command).is_empty(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_numbered_and_plural_names_pass() {
+        for command in ["pip install markdown2", "pip install jinja", "pip install websocket"] {
+            assert!(suspects(command).is_empty(), "{}", command);
+        }
+        assert!(!suspects("pip install jinja3").is_empty());
+        assert!(!suspects("npm install expres").is_empty());
+    }
+
+    #[test]
+    fn test_actions() {
+        let reply = "Install it:\n```\npip install requestss numpy\n```\nThen run it.";
+        let filter = PackageFilter::new(&PackagePolicy::default(), &[]);
+        assert_eq!(
+            filter.apply(reply).text,
+            "Install it:\n```\npip install requestss numpy  # ⚠️ Molt-Guard: requestss looks like a typosquat of requests\n```\nThen run it."
+        );
+        let filter = PackageFilter::new(&PackagePolicy { action: PackageAction::Block }, &[]);
+        assert!(filter.apply(reply).blocked);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("requests", "reqeusts"), 1);
+        assert_eq!(edit_distance("flask", "flask"), 0);
+        assert_eq!(edit_distance("django", "djnago"), 1);
+        assert_eq!(edit_distance("numpy", "numba"), 2);
+    }
+}
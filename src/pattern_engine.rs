@@ -0,0 +1,91 @@
+use crate::filters::Finding;
+use regex::{Regex, RegexSet};
+
+/// One detector inside a `PatternEngine`. If the regex has a capture group
+/// named `secret`, only that group is reported, so surrounding context such as
+/// a `user:` prefix can be part of the match without being redacted.
+pub struct Pattern {
+    pub kind: &'static str,
+    pub regex: &'static str,
+    pub confidence: f32,
+}
+
+/// Regex scanner shared by the regex-based filters.
+///
+/// A `RegexSet` over all patterns first finds which of them occur anywhere in
+/// the input in a single pass; only those are then run to collect match
+/// positions. On typical responses nothing matches and the input is read once.
+pub struct PatternEngine {
+    filter: String,
+    set: RegexSet,
+    patterns: Vec<(Pattern, Regex, Option<usize>)>,
+}
+
+impl PatternEngine {
+    pub fn new(filter: &str, patterns: Vec<Pattern>) -> Result<Self, regex::Error> {
+        let set = RegexSet::new(patterns.iter().map(|p| p.regex))?;
+        let patterns = patterns.into_iter()
+            .map(|p| {
+                let regex = Regex::new(p.regex)?;
+                let group = regex.capture_names().position(|name| name == Some("secret"));
+                Ok((p, regex, group))
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self { filter: filter.to_string(), set, patterns })
+    }
+
+    /// Findings in priority order: by pattern (earlier patterns first), then
+    /// by position.
+    pub fn scan(&self, input: &str) -> Vec<Finding> {
+        self.set.matches(input)
+            .into_iter()
+            .flat_map(|i| {
+                let (pattern, regex, group) = &self.patterns[i];
+                let spans: Vec<(usize, usize)> = match group {
+                    Some(group) => regex.captures_iter(input)
+                        .filter_map(|c| c.get(*group))
+                        .map(|m| (m.start(), m.end()))
+                        .collect(),
+                    None => regex.find_iter(input).map(|m| (m.start(), m.end())).collect(),
+                };
+                spans.into_iter().map(move |(start, end)| Finding {
+                    filter: self.filter.clone(),
+                    kind: pattern.kind.to_string(),
+                    start,
+                    end,
+                    confidence: pattern.confidence,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_matching_patterns_reported_in_priority_order() {
+        let engine = PatternEngine::new("test", vec![
+            Pattern { kind: "word", regex: r"\bkey\b", confidence: 0.5 },
+            Pattern { kind: "never", regex: r"zzz\d", confidence: 0.5 },
+            Pattern { kind: "digits", regex: r"\d+", confidence: 0.9 },
+        ]).unwrap();
+
+        let findings = engine.scan("42 key 7 key");
+        let kinds: Vec<&str> = findings.iter().map(|f| f.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["word", "word", "digits", "digits"]);
+        assert_eq!((findings[2].start, findings[2].end), (0, 2));
+    }
+
+    #[test]
+    fn test_secret_group_narrows_span() {
+        let engine = PatternEngine::new("test", vec![
+            Pattern { kind: "password", regex: r"pass=(?P<secret>\w+)", confidence: 0.9 },
+        ]).unwrap();
+
+        let input = "user=bob pass=hunter2";
+        let findings = engine.scan(input);
+        assert_eq!(&input[findings[0].start..findings[0].end], "hunter2");
+    }
+}
@@ -1,27 +1,204 @@
-use regex::Regex;
+use crate::filters::{Filter, Finding};
+use crate::pattern_engine::{Pattern, PatternEngine};
+use crate::pii_locales;
+use anyhow::{Result, anyhow};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Detectors used in every locale, in priority order. Most patterns only find
+/// candidates; `is_valid` then rejects lookalikes (wrong checksum, impossible
+/// dates). National identifiers live in `pii_locales`.
+fn detectors() -> Vec<Pattern> {
+    vec![
+        Pattern { kind: "email", regex: r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}", confidence: 0.8 },
+        // 13-19 digits, optionally grouped by spaces or dashes; Luhn-checked
+        Pattern { kind: "credit_card", regex: r"\b\d(?:[ -]?\d){12,18}\b", confidence: 0.9 },
+        // Country code, check digits, then the account number in groups of
+        // four as usually printed; mod-97 checked
+        Pattern { kind: "iban", regex: r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b", confidence: 0.9 },
+        Pattern { kind: "mac_address", regex: r"\b[0-9A-Fa-f]{2}(?:[:-][0-9A-Fa-f]{2}){5}\b", confidence: 0.7 },
+        Pattern { kind: "ipv6", regex: r"(?:[0-9A-Fa-f]{0,4}:){2,7}[0-9A-Fa-f]{0,4}(?:%[0-9A-Za-z]+)?", confidence: 0.7 },
+        Pattern { kind: "ipv4", regex: r"\b(?:\d{1,3}\.){3}\d{1,3}\b", confidence: 0.7 },
+        Pattern { kind: "date_of_birth", regex: r"(?i)\b(?:date of birth|birth ?date|d\.?o\.?b\.?|born(?: on)?)\W{0,3}(?P<secret>\d{1,4}[-/.]\d{1,2}[-/.]\d{1,4})\b", confidence: 0.8 },
+        Pattern { kind: "passport", regex: r"(?i)\bpassport(?:\s*(?:no\.?|number|num|#|id))?\W{0,3}(?P<secret>[A-Z0-9]{6,9})\b", confidence: 0.8 },
+        // US format with separators, or international E.164 with a leading +
+        Pattern { kind: "phone", regex: r"(?:\(\d{3}\)\s?|\b\d{3}[-.\s])\d{3}[-.\s]\d{4}\b|\+[1-9]\d{0,2}(?:[ .-]?\d{2,4}){2,6}\b", confidence: 0.8 },
+    ]
+}
+
+/// Detectors that only run when named, as the pseudonymizer's kinds do:
+/// their matches are too common in ordinary replies to redact by default.
+fn opt_in_detectors() -> Vec<Pattern> {
+    vec![
+        // Names only where the text introduces one, e.g. "my name is Jane Roe"
+        Pattern { kind: "person_name", regex: r"(?:\b(?:[Mm]y name is|[Nn]ame:|Mr\.|Mrs\.|Ms\.|Dr\.)\s+)(?P<secret>[A-Z][a-z]+(?: [A-Z][a-z]+){0,2})", confidence: 0.6 },
+    ]
+}
+
+/// Kinds of all built-in detectors including every locale pack and the
+/// opt-in detectors, e.g. for validating configuration.
+pub fn detector_kinds() -> Vec<&'static str> {
+    detectors().iter().chain(&opt_in_detectors()).map(|p| p.kind).chain(pii_locales::detector_kinds()).collect()
+}
+
+fn digits(value: &str) -> Vec<u32> {
+    value.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits.iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn is_credit_card(value: &str) -> bool {
+    let digits = digits(value);
+    // Issuer prefixes: Visa, Mastercard, Amex, Diners, Discover, JCB, UnionPay.
+    let known_issuer = matches!(digits.first(), Some(2..=6));
+    // Printed cards are grouped 4-4-4-4(-3), or 4-6-5 / 4-6-4 for Amex and
+    // Diners; other groupings are account or reference numbers.
+    let groups: Vec<usize> = value.split([' ', '-']).map(str::len).collect();
+    let grouping = matches!(groups.as_slice(), [_] | [4, 4, 4, 4] | [4, 4, 4, 4, 3] | [4, 6, 5] | [4, 6, 4]);
+    (13..=19).contains(&digits.len())
+        && known_issuer
+        && grouping
+        && digits.iter().any(|&d| d != digits[0])
+        && luhn_valid(&digits)
+}
+
+fn is_iban(value: &str) -> bool {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(15..=34).contains(&compact.len()) {
+        return false;
+    }
+    // Move the country code and check digits to the end, map letters to
+    // 10..35 and take the whole number mod 97 piecewise.
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let Some(value) = c.to_digit(36) else {
+            return false;
+        };
+        remainder = if value > 9 { (remainder * 100 + value) % 97 } else { (remainder * 10 + value) % 97 };
+    }
+    remainder == 1
+}
+
+fn is_mac_address(value: &str) -> bool {
+    // Separators must be consistent, and all-zero / broadcast are placeholders.
+    let separators = value.chars().filter(|c| *c == ':' || *c == '-').collect::<Vec<_>>();
+    let lower = value.to_ascii_lowercase();
+    separators.windows(2).all(|w| w[0] == w[1])
+        && !matches!(lower.replace('-', ":").as_str(), "00:00:00:00:00:00" | "ff:ff:ff:ff:ff:ff")
+}
+
+fn is_ipv6(value: &str) -> bool {
+    let address = value.split('%').next().unwrap_or_default();
+    // Times such as 12:30:45 fail to parse; paths like `std::io` or `a::b`
+    // do parse, but real addresses have at least one group of 3+ digits.
+    address.matches(':').count() >= 2
+        && address.split(':').any(|group| group.len() >= 3)
+        && address.parse::<Ipv6Addr>().is_ok_and(|ip| !ip.is_loopback() && !ip.is_unspecified())
+}
+
+fn is_ipv4(value: &str) -> bool {
+    value.parse::<Ipv4Addr>().is_ok_and(|ip| !ip.is_unspecified() && !ip.is_loopback())
+}
+
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split(['-', '/', '.']).collect();
+    let [a, b, c] = parts[..] else {
+        return false;
+    };
+    let plausible = |year: &str, x: &str, y: &str| {
+        let (Ok(year), Ok(x), Ok(y)) = (year.parse::<u32>(), x.parse::<u32>(), y.parse::<u32>()) else {
+            return false;
+        };
+        let day_month = |d: u32, m: u32| (1..=31).contains(&d) && (1..=12).contains(&m);
+        year.to_string().len() == 4 && (1900..=2100).contains(&year) && (day_month(x, y) || day_month(y, x))
+    };
+    // ISO order (year first) or day/month first with the year last.
+    (a.len() == 4 && plausible(a, b, c)) || (c.len() == 4 && plausible(c, a, b))
+}
+
+fn is_phone(value: &str) -> bool {
+    let count = digits(value).len();
+    if value.starts_with('+') { (8..=15).contains(&count) } else { count == 10 }
+}
+
+fn is_valid(kind: &str, value: &str) -> bool {
+    match kind {
+        "credit_card" => is_credit_card(value),
+        "iban" => is_iban(value),
+        "mac_address" => is_mac_address(value),
+        "ipv6" => is_ipv6(value),
+        "ipv4" => is_ipv4(value),
+        "date_of_birth" => is_date(value),
+        "passport" => value.bytes().any(|b| b.is_ascii_digit()),
+        "phone" => is_phone(value),
+        _ => pii_locales::is_valid(kind, value).unwrap_or(true),
+    }
+}
 
 pub struct PiiFilter {
-    patterns: Vec<Regex>,
+    engine: PatternEngine,
 }
 
 impl PiiFilter {
+    /// Common detectors plus the US pack.
     pub fn new() -> Self {
-        // Initial PII patterns. In a refined version, this would use a lightweight LLM (candle).
-        let patterns = vec![
-            // Email address
-            Regex::new(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}").unwrap(),
-            // Phone number (US format)
-            Regex::new(r"\b\d{3}[-.]?\d{3}[-.]?\d{4}\b").unwrap(),
-        ];
-        Self { patterns }
+        Self::with_locales(&["us".to_string()], &[]).expect("built-in detectors are valid")
     }
 
-    pub fn redact(&self, input: &str) -> String {
-        let mut output = input.to_string();
-        for pattern in &self.patterns {
-            output = pattern.replace_all(&output, "[PII_REDACTED]").to_string();
+    /// Common detectors plus the packs for `locales`, except detectors whose
+    /// kind is listed in `disabled`. Kinds belonging to other filters are
+    /// ignored.
+    pub fn with_locales(locales: &[String], disabled: &[String]) -> Result<Self> {
+        Self::with_opt_in(locales, disabled, &[])
+    }
+
+    /// Like `with_locales`, also running the opt-in detectors named in
+    /// `opt_in`.
+    pub fn with_opt_in(locales: &[String], disabled: &[String], opt_in: &[String]) -> Result<Self> {
+        let mut patterns = Vec::new();
+        // National identifiers carry checksums, so they take precedence over
+        // the looser common patterns such as phone numbers.
+        for locale in locales {
+            patterns.extend(pii_locales::detectors(locale).ok_or_else(|| anyhow!("Unknown PII locale '{}'", locale))?);
         }
-        output
+        patterns.extend(detectors());
+        patterns.extend(opt_in_detectors().into_iter().filter(|p| opt_in.iter().any(|k| k == p.kind)));
+        patterns.retain(|p| !disabled.iter().any(|d| d == p.kind));
+        Ok(Self { engine: PatternEngine::new("pii", patterns)? })
+    }
+
+    pub fn redact(&self, input: &str) -> String {
+        self.apply(input).text
+    }
+}
+
+impl Default for PiiFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filter for PiiFilter {
+    fn name(&self) -> &str {
+        "pii"
+    }
+
+    fn scan(&self, input: &str) -> Vec<Finding> {
+        self.engine.scan(input)
+            .into_iter()
+            .filter(|f| is_valid(&f.kind, &input[f.start..f.end]))
+            .collect()
+    }
+
+    fn placeholder(&self, _finding: &Finding, _value: &str) -> String {
+        "[PII_REDACTED]".to_string()
     }
 }
 
@@ -29,6 +206,10 @@ impl PiiFilter {
 mod tests {
     use super::*;
 
+    fn kinds(filter: &PiiFilter, input: &str) -> Vec<String> {
+        filter.apply(input).findings.into_iter().map(|f| f.kind).collect()
+    }
+
     #[test]
     fn test_redact_pii_email() {
         let filter = PiiFilter::new();
@@ -46,4 +227,84 @@ mod tests {
         assert!(output.contains("[PII_REDACTED]"));
         assert!(!output.contains("555-123"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_phone_formats() {
+        let filter = PiiFilter::new();
+        for input in ["(555) 123-4567", "555.123.4567", "+44 20 7946 0958", "+14155552671"] {
+            assert_eq!(kinds(&filter, input), vec!["phone"], "{}", input);
+        }
+        // Bare digit runs are order numbers and timestamps, not phone numbers.
+        assert!(kinds(&filter, "Order 5551234567 shipped at 1712345678").is_empty());
+    }
+
+    #[test]
+    fn test_checksummed_numbers() {
+        let filter = PiiFilter::new();
+        assert_eq!(kinds(&filter, "Card: 4111 1111 1111 1111"), vec!["credit_card"]);
+        assert_eq!(kinds(&filter, "Card: 5500-0000-0000-0004"), vec!["credit_card"]);
+        assert!(kinds(&filter, "Card: 4111 1111 1111 1112").is_empty(), "fails Luhn");
+        assert!(kinds(&filter, "Tracking 1000000000000000").is_empty(), "no issuer");
+        assert_eq!(kinds(&filter, "Amex 3782 822463 10005"), vec!["credit_card"]);
+
+        assert_eq!(kinds(&filter, "IBAN DE89 3704 0044 0532 0130 00"), vec!["iban"]);
+        assert_eq!(kinds(&filter, "GB82WEST12345698765432"), vec!["iban"]);
+        assert!(kinds(&filter, "DE89 3704 0044 0532 0130 01").is_empty(), "fails mod-97");
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let filter = PiiFilter::new();
+        assert_eq!(kinds(&filter, "SSN 123-45-6789"), vec!["ssn"]);
+        assert!(kinds(&filter, "SSN 666-45-6789").is_empty());
+        assert!(kinds(&filter, "SSN 123-00-6789").is_empty());
+
+        let input = "Date of birth: 1985-07-14, passport no. X1234567";
+        assert_eq!(kinds(&filter, input), vec!["date_of_birth", "passport"]);
+        assert_eq!(filter.redact(input), "Date of birth: [PII_REDACTED], passport no. [PII_REDACTED]");
+        assert!(kinds(&filter, "DOB 1985-14-31").is_empty());
+        assert!(kinds(&filter, "The passport office opens at nine").is_empty());
+
+        assert!(kinds(&filter, "Hi, my name is Jane Roe. Ask Dr. Smith.").is_empty(), "names are opt-in");
+        let filter = PiiFilter::with_opt_in(&["us".to_string()], &[], &["person_name".to_string()]).unwrap();
+        assert_eq!(filter.redact("Hi, my name is Jane Roe."), "Hi, my name is [PII_REDACTED].");
+        assert!(kinds(&filter, "The name is not important").is_empty());
+    }
+
+    #[test]
+    fn test_network_addresses() {
+        let filter = PiiFilter::new();
+        assert_eq!(kinds(&filter, "client 203.0.113.42 connected"), vec!["ipv4"]);
+        assert!(kinds(&filter, "version 999.1.2.3").is_empty());
+        assert!(kinds(&filter, "listening on 127.0.0.1").is_empty());
+
+        assert_eq!(kinds(&filter, "from 2001:db8:85a3::8a2e:370:7334"), vec!["ipv6"]);
+        assert_eq!(kinds(&filter, "nic 00:1A:2B:3C:4D:5E"), vec!["mac_address"]);
+        assert!(kinds(&filter, "nic 00:1A-2B:3C:4D:5E").is_empty());
+        assert!(kinds(&filter, "meet at 12:30:45").is_empty());
+        assert!(kinds(&filter, "use std::net::Ipv4Addr; let a = Ab::c;").is_empty());
+    }
+
+    #[test]
+    fn test_locale_packs() {
+        let filter = PiiFilter::with_locales(&["uk".to_string(), "in".to_string()], &[]).unwrap();
+        let input = "NI number AB 12 34 56 C, postcode SW1A 1AA, Aadhaar 2345 6789 0124, PAN ABCPE1234F";
+        assert_eq!(kinds(&filter, input), vec!["uk_nino", "uk_postcode", "in_aadhaar", "in_pan"]);
+        assert!(kinds(&filter, "SSN 123-45-6789").is_empty(), "US pack not selected");
+        assert!(kinds(&filter, "Aadhaar 2345 6789 0123").is_empty(), "fails Verhoeff");
+
+        let filter = PiiFilter::with_locales(&["de".to_string(), "fr".to_string(), "br".to_string()], &[]).unwrap();
+        let input = "Steuer-ID 86095742719, NIR 1 85 05 78 006 084 91, CPF 529.982.247-25";
+        assert_eq!(kinds(&filter, input), vec!["de_tax_id", "fr_nir", "br_cpf"]);
+        assert!(kinds(&filter, "Steuer-ID 86095742718, CPF 529.982.247-24").is_empty());
+
+        assert!(PiiFilter::with_locales(&["xx".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_detectors_toggleable() {
+        let filter = PiiFilter::with_locales(&[], &["ipv4".to_string(), "jwt".to_string()]).unwrap();
+        assert!(kinds(&filter, "client 203.0.113.42").is_empty());
+        assert_eq!(kinds(&filter, "john.doe@example.com"), vec!["email"]);
+    }
+}
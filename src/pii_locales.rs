@@ -0,0 +1,192 @@
+use crate::pattern_engine::Pattern;
+
+/// Locales with a detector pack, selectable per profile via `pii_locales`.
+pub const LOCALES: &[&str] = &["us", "uk", "de", "fr", "in", "br"];
+
+/// National identifiers for `locale`, or `None` for an unknown locale.
+pub fn detectors(locale: &str) -> Option<Vec<Pattern>> {
+    Some(match locale {
+        "us" => vec![
+            Pattern { kind: "ssn", regex: r"\b\d{3}-\d{2}-\d{4}\b", confidence: 0.85 },
+        ],
+        "uk" => vec![
+            // National Insurance number, e.g. AB 12 34 56 C
+            Pattern { kind: "uk_nino", regex: r"\b[A-CEGHJ-PR-TW-Z][A-CEGHJ-NPR-TW-Z] ?\d{2} ?\d{2} ?\d{2} ?[A-D]\b", confidence: 0.85 },
+            Pattern { kind: "uk_postcode", regex: r"\b(?:[A-Z]{1,2}\d[A-Z\d]?|GIR) ?\d[ABD-HJLNP-UW-Z]{2}\b", confidence: 0.6 },
+        ],
+        "de" => vec![
+            // Steuerliche Identifikationsnummer, 11 digits
+            Pattern { kind: "de_tax_id", regex: r"\b[1-9]\d(?: ?\d{3}){3}\b", confidence: 0.8 },
+        ],
+        "fr" => vec![
+            // Numéro de sécurité sociale (NIR) with its two-digit key
+            Pattern { kind: "fr_nir", regex: r"\b[12] ?\d{2} ?(?:0[1-9]|1[0-2]|[2-9]\d) ?(?:\d{2}|2[AB]) ?\d{3} ?\d{3} ?\d{2}\b", confidence: 0.85 },
+        ],
+        "in" => vec![
+            Pattern { kind: "in_aadhaar", regex: r"\b[2-9]\d{3} ?\d{4} ?\d{4}\b", confidence: 0.85 },
+            Pattern { kind: "in_pan", regex: r"\b[A-Z]{3}[ABCFGHLJPT][A-Z]\d{4}[A-Z]\b", confidence: 0.8 },
+        ],
+        "br" => vec![
+            Pattern { kind: "br_cpf", regex: r"\b\d{3}\.?\d{3}\.?\d{3}-?\d{2}\b", confidence: 0.85 },
+        ],
+        _ => return None,
+    })
+}
+
+/// Kinds from every pack, whether or not it is selected.
+pub fn detector_kinds() -> Vec<&'static str> {
+    LOCALES.iter()
+        .flat_map(|locale| detectors(locale).unwrap_or_default())
+        .map(|p| p.kind)
+        .collect()
+}
+
+fn digits(value: &str) -> Vec<u32> {
+    value.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+fn is_ssn(value: &str) -> bool {
+    let mut parts = value.split('-').map(|p| p.parse::<u32>().unwrap_or(0));
+    let (area, group, serial) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    // Never issued: area 000, 666 or 900-999, group 00, serial 0000.
+    area != 0 && area != 666 && area < 900 && group != 0 && serial != 0
+}
+
+fn is_uk_nino(value: &str) -> bool {
+    // Prefixes never allocated, or reserved for temporary numbers.
+    !matches!(&value[..2], "BG" | "GB" | "KN" | "NK" | "NT" | "TN" | "ZZ")
+}
+
+/// ISO 7064 MOD 11,10 check digit, plus the rule that exactly one digit of the
+/// first ten repeats (twice or three times).
+fn is_de_tax_id(value: &str) -> bool {
+    let digits = digits(value);
+    let mut counts = [0; 10];
+    for &d in &digits[..10] {
+        counts[d as usize] += 1;
+    }
+    let repeated = counts.iter().filter(|&&c| c > 1).count();
+    if repeated != 1 || counts.iter().any(|&c| c > 3) {
+        return false;
+    }
+
+    let mut product = 10;
+    for &d in &digits[..10] {
+        let sum = match (d + product) % 10 {
+            0 => 10,
+            sum => sum,
+        };
+        product = (sum * 2) % 11;
+    }
+    let check = match 11 - product {
+        10 => 0,
+        check => check,
+    };
+    check == digits[10]
+}
+
+/// The key is 97 minus the first 13 characters mod 97, with the Corsican
+/// departments 2A and 2B counted as 19 and 18.
+fn is_fr_nir(value: &str) -> bool {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    let (number, key) = compact.split_at(13);
+    let number = number.replace("2A", "19").replace("2B", "18");
+    match (number.parse::<u64>(), key.parse::<u64>()) {
+        (Ok(number), Ok(key)) => 97 - number % 97 == key,
+        _ => false,
+    }
+}
+
+const VERHOEFF_D: [[usize; 10]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+    [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+    [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+    [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+    [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+    [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+    [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+    [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+    [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+];
+
+const VERHOEFF_P: [[usize; 10]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+    [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+    [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+    [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+    [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+    [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+    [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+];
+
+/// Aadhaar numbers end in a Verhoeff check digit.
+fn is_in_aadhaar(value: &str) -> bool {
+    let check = digits(value).iter()
+        .rev()
+        .enumerate()
+        .fold(0, |c, (i, &d)| VERHOEFF_D[c][VERHOEFF_P[i % 8][d as usize]]);
+    check == 0
+}
+
+/// Two mod-11 check digits over the preceding nine and ten digits.
+fn is_br_cpf(value: &str) -> bool {
+    let digits = digits(value);
+    if digits.iter().all(|&d| d == digits[0]) {
+        return false;
+    }
+    [9, 10].iter().all(|&n| {
+        let sum: u32 = digits[..n].iter().enumerate().map(|(i, &d)| d * (n as u32 + 1 - i as u32)).sum();
+        (sum * 10 % 11) % 10 == digits[n]
+    })
+}
+
+/// Validation for locale kinds; `None` if `kind` is not from a locale pack.
+pub fn is_valid(kind: &str, value: &str) -> Option<bool> {
+    Some(match kind {
+        "ssn" => is_ssn(value),
+        "uk_nino" => is_uk_nino(value),
+        "de_tax_id" => is_de_tax_id(value),
+        "fr_nir" => is_fr_nir(value),
+        "in_aadhaar" => is_in_aadhaar(value),
+        "br_cpf" => is_br_cpf(value),
+        "uk_postcode" | "in_pan" => true,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert!(is_de_tax_id("86 095 742 719"));
+        assert!(!is_de_tax_id("86095742718"));
+        assert!(!is_de_tax_id("12345678903"), "no repeated digit");
+
+        assert!(is_fr_nir("1 85 05 78 006 084 91"));
+        assert!(is_fr_nir("2 69 04 99 350 012 82"));
+        assert!(!is_fr_nir("185057800608492"));
+
+        assert!(is_in_aadhaar("2345 6789 0124"));
+        assert!(!is_in_aadhaar("2345 6789 0123"));
+
+        assert!(is_br_cpf("529.982.247-25"));
+        assert!(!is_br_cpf("529.982.247-24"));
+        assert!(!is_br_cpf("111.111.111-11"));
+
+        assert!(!is_uk_nino("GB123456A"));
+        assert!(is_ssn("123-45-6789"));
+        assert!(!is_ssn("900-45-6789"));
+    }
+
+    #[test]
+    fn test_every_pack_kind_is_known() {
+        let kinds = detector_kinds();
+        assert!(kinds.contains(&"in_pan") && kinds.contains(&"ssn"));
+        assert!(kinds.iter().all(|k| is_valid(k, "AAA0000000000000").is_some()));
+        assert!(detectors("xx").is_none());
+    }
+}
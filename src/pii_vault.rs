@@ -0,0 +1,255 @@
+use crate::api_types::Message;
+use crate::filters::{FilterOptions, FilterPipeline};
+use crate::pii_filter::{self, PiiFilter};
+use crate::tool_policy::rewrite_arguments;
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Vault tokens are double-bracketed so they never collide with the
+/// `[EMAIL_1]` tokens of the `typed` output redaction style.
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\[([A-Z][A-Z0-9_]*_\d+)\]\]").unwrap());
+
+/// Session vaults idle for longer than this are forgotten.
+const VAULT_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Carries the id of a session vault: issued by the proxy in the response,
+/// sent back by the client on the conversation's next turn.
+pub const VAULT_HEADER: &str = "x-molt-guard-vault";
+
+/// How long a mapping lives.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultScope {
+    /// A fresh vault per request.
+    #[default]
+    Request,
+    /// One vault per conversation, so a value keeps its token across turns.
+    /// Conversations are identified by an id the proxy issues
+    /// (`VAULT_HEADER`), only valid with the API key it was issued to.
+    Session,
+}
+
+/// Per-profile input pseudonymization settings.
+///
+/// ```json
+/// { "pseudonymize": { "kinds": ["email", "phone", "person_name"], "scope": "session" } }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PseudonymizeConfig {
+    /// PII detector kinds to replace before the prompt reaches the model.
+    pub kinds: Vec<String>,
+    pub scope: VaultScope,
+}
+
+/// Original values behind the tokens sent to the model.
+#[derive(Clone, Debug, Default)]
+pub struct Vault {
+    /// Id of a session vault, to return to the client.
+    id: Option<String>,
+    tokens: HashMap<String, String>,
+    values: HashMap<String, String>,
+    counters: HashMap<String, usize>,
+}
+
+impl Vault {
+    fn token(&mut self, kind: &str, value: &str) -> String {
+        if let Some(token) = self.tokens.get(value) {
+            return token.clone();
+        }
+        let counter = self.counters.entry(kind.to_string()).or_default();
+        *counter += 1;
+        let token = format!("[[{}_{}]]", kind.to_ascii_uppercase(), counter);
+        self.tokens.insert(value.to_string(), token.clone());
+        self.values.insert(token.clone(), value.to_string());
+        token
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The id the client must send back in `VAULT_HEADER` to keep using this
+    /// vault; `None` for a request-scoped vault.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Puts the original values back in place of their tokens. Tokens the
+    /// vault does not know, e.g. ones the model made up, are left as they are.
+    pub fn restore(&self, text: &str) -> String {
+        self.restore_with(text, |value| value.to_string())
+    }
+
+    /// Like `restore`, for a raw JSON body: values are JSON-escaped so they
+    /// cannot break out of the string they are restored into.
+    pub fn restore_in_json(&self, body: &str) -> String {
+        self.restore_with(body, |value| {
+            let quoted = serde_json::to_string(value).unwrap_or_default();
+            quoted[1..quoted.len() - 1].to_string()
+        })
+    }
+
+    fn restore_with(&self, text: &str, encode: impl Fn(&str) -> String) -> String {
+        if self.is_empty() {
+            return text.to_string();
+        }
+        TOKEN.replace_all(text, |caps: &regex::Captures| {
+            match self.values.get(&caps[0]) {
+                Some(value) => encode(value),
+                None => caps[0].to_string(),
+            }
+        }).into_owned()
+    }
+}
+
+/// Replaces PII in requests with stable tokens and restores it in responses,
+/// so the model never sees the original values.
+pub struct Pseudonymizer {
+    detector: FilterPipeline,
+    scope: VaultScope,
+    /// Session vaults by owner (the client's API key identity) and id.
+    vaults: Mutex<HashMap<(String, String), (Instant, Vault)>>,
+}
+
+impl Pseudonymizer {
+    /// `options` supplies the profile's PII locales; only `config.kinds` are
+    /// pseudonymized.
+    pub fn new(config: &PseudonymizeConfig, options: &FilterOptions) -> Result<Self> {
+        let known = pii_filter::detector_kinds();
+        if let Some(unknown) = config.kinds.iter().find(|k| !known.contains(&k.as_str())) {
+            return Err(anyhow!("Cannot pseudonymize unknown PII kind '{}'", unknown));
+        }
+        let disabled: Vec<String> = known.iter()
+            .filter(|k| !config.kinds.iter().any(|c| c == *k))
+            .map(|k| k.to_string())
+            .collect();
+        let filter = PiiFilter::with_opt_in(&options.pii_locales, &disabled, &config.kinds)?;
+        Ok(Self {
+            detector: FilterPipeline::new(vec![Arc::new(filter)]),
+            scope: config.scope,
+            vaults: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Runs `pseudonymize` on the request's vault and returns a copy to
+    /// restore the reply with. With session scope this is the vault `id`
+    /// names if it was issued to `owner`, or a new one with a fresh id; it is
+    /// updated under the lock, so concurrent turns do not lose tokens.
+    pub fn with_vault(&self, owner: &str, id: Option<&str>, pseudonymize: impl FnOnce(&Self, &mut Vault)) -> Vault {
+        if self.scope == VaultScope::Request {
+            let mut vault = Vault::default();
+            pseudonymize(self, &mut vault);
+            return vault;
+        }
+        let mut vaults = self.vaults.lock().unwrap();
+        vaults.retain(|_, (seen, _)| seen.elapsed() < VAULT_TTL);
+        let key = id.map(|id| (owner.to_string(), id.to_string()))
+            .filter(|key| vaults.contains_key(key))
+            .unwrap_or_else(|| (owner.to_string(), uuid::Uuid::new_v4().to_string()));
+        let (seen, vault) = vaults.entry(key.clone())
+            .or_insert_with(|| (Instant::now(), Vault { id: Some(key.1), ..Vault::default() }));
+        *seen = Instant::now();
+        pseudonymize(self, vault);
+        vault.clone()
+    }
+
+    pub fn pseudonymize(&self, vault: &mut Vault, text: &str) -> String {
+        let findings = self.detector.apply(text).findings;
+        if findings.is_empty() {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for finding in findings {
+            out.push_str(&text[last..finding.start]);
+            out.push_str(&vault.token(&finding.kind, &text[finding.start..finding.end]));
+            last = finding.end;
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+
+    /// Pseudonymizes the text of every message and the argument values of
+    /// the tool calls in the history, which carry restored values back.
+    pub fn pseudonymize_messages(&self, vault: &mut Vault, messages: &mut [Message]) {
+        for message in messages {
+            message.content = self.pseudonymize(vault, &message.content);
+            for call in message.tool_calls.iter_mut().flatten() {
+                *call = rewrite_arguments(call, |value| self.pseudonymize(vault, value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudonymizer(scope: VaultScope) -> Pseudonymizer {
+        let config = PseudonymizeConfig {
+            kinds: vec!["email".to_string(), "phone".to_string(), "person_name".to_string()],
+            scope,
+        };
+        Pseudonymizer::new(&config, &FilterOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pseudonymizer = pseudonymizer(VaultScope::Request);
+        let mut vault = Vault::default();
+        let input = "My name is Jane Roe, mail jane@corp.com or call 555-123-4567. Again: jane@corp.com. Card 4111 1111 1111 1111.";
+        let masked = pseudonymizer.pseudonymize(&mut vault, input);
+        assert_eq!(
+            masked,
+            "My name is [[PERSON_NAME_1]], mail [[EMAIL_1]] or call [[PHONE_1]]. Again: [[EMAIL_1]]. Card 4111 1111 1111 1111."
+        );
+
+        let reply = "Sure [[PERSON_NAME_1]], I will write to [[EMAIL_1]] and not [[EMAIL_9]].";
+        assert_eq!(vault.restore(reply), "Sure Jane Roe, I will write to jane@corp.com and not [[EMAIL_9]].");
+    }
+
+    #[test]
+    fn test_restore_in_json_escapes() {
+        let mut vault = Vault::default();
+        let token = vault.token("email", "a\"b@corp.com");
+        let body = format!(r#"{{"content":"hi {}"}}"#, token);
+        let restored: serde_json::Value = serde_json::from_str(&vault.restore_in_json(&body)).unwrap();
+        assert_eq!(restored["content"], "hi a\"b@corp.com");
+    }
+
+    #[test]
+    fn test_session_scope_keeps_tokens_stable() {
+        let pseudonymizer = pseudonymizer(VaultScope::Session);
+        let first = pseudonymizer.with_vault("key-a", None, |p, vault| {
+            p.pseudonymize(vault, "a@corp.com");
+        });
+        let id = first.id().unwrap();
+
+        let mut masked = String::new();
+        pseudonymizer.with_vault("key-a", Some(id), |p, vault| masked = p.pseudonymize(vault, "b@corp.com, a@corp.com"));
+        assert_eq!(masked, "[[EMAIL_2]], [[EMAIL_1]]");
+
+        // Another key, or an id the proxy did not issue, gets a fresh vault.
+        let other = pseudonymizer.with_vault("key-b", Some(id), |_, _| {});
+        assert!(other.is_empty());
+        assert_ne!(other.id(), Some(id));
+        assert!(pseudonymizer.with_vault("key-a", Some("chosen-by-client"), |_, _| {}).id() != Some("chosen-by-client"));
+
+        let request_scoped = self::pseudonymizer(VaultScope::Request);
+        let vault = request_scoped.with_vault("key-a", None, |p, vault| {
+            p.pseudonymize(vault, "a@corp.com");
+        });
+        assert!(vault.id().is_none());
+    }
+
+    #[test]
+    fn test_unknown_kind_rejected() {
+        let config = PseudonymizeConfig { kinds: vec!["aws_access_key".to_string()], scope: VaultScope::Request };
+        assert!(Pseudonymizer::new(&config, &FilterOptions::default()).is_err());
+    }
+}
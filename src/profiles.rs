@@ -0,0 +1,324 @@
+use crate::block_response::BlockResponseConfig;
+use crate::custom_filter::CustomFilter;
+use crate::filters::{validate_options, CustomFilters, FilterOptions, FilterPipeline};
+use crate::image_policy::{ImagePolicy, ImagePolicyConfig};
+use crate::input_dlp::{InputDlp, InputFilterConfig};
+use crate::pii_vault::{PseudonymizeConfig, Pseudonymizer};
+use crate::prompt_leak::{PromptLeakConfig, PromptLeakGuard};
+use crate::tool_policy::{ToolPolicy, ToolPolicyConfig};
+use anyhow::{Context, Result, anyhow};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const DEFAULT_PROFILE: &str = "default";
+/// Room for the messages of a request besides its images: axum's default
+/// body limit.
+const TEXT_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Per-profile settings as written in the config file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Output filters by name, in order of precedence.
+    pub filters: Vec<String>,
+    #[serde(flatten)]
+    pub filter_options: FilterOptions,
+    /// Filters applied to incoming messages, and what to do on a match.
+    pub input_filters: InputFilterConfig,
+    /// Limits on images attached to requests.
+    pub images: ImagePolicyConfig,
+    /// PII to replace with tokens before requests reach the model.
+    pub pseudonymize: PseudonymizeConfig,
+    /// Compare replies with the request's system prompt; off if unset.
+    pub prompt_leak: Option<PromptLeakConfig>,
+    /// Policy on the tool calls of replies; calls pass unchecked if unset.
+    pub tools: Option<ToolPolicyConfig>,
+    /// How blocked requests and withheld replies are reported.
+    pub block_response: BlockResponseConfig,
+    /// Run every policy but only log and count what it would have done;
+    /// traffic passes unchanged.
+    pub shadow: bool,
+    /// Let clients ask for the verdict of every stage (`X-Molt-Guard-Explain`)
+    /// and for dry runs (`X-Molt-Guard-Dry-Run`).
+    pub explain: bool,
+    /// Let clients read reports on every profile (`/molt-guard/shadow`).
+    pub admin: bool,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            filters: vec!["secrets".to_string(), "pii".to_string()],
+            filter_options: FilterOptions::default(),
+            input_filters: InputFilterConfig::default(),
+            images: ImagePolicyConfig::default(),
+            pseudonymize: PseudonymizeConfig::default(),
+            prompt_leak: None,
+            tools: None,
+            block_response: BlockResponseConfig::default(),
+            shadow: false,
+            explain: false,
+            admin: false,
+        }
+    }
+}
+
+/// Top-level config file (`MOLT_GUARD_CONFIG`).
+///
+/// ```json
+/// {
+///   "profiles": {
+///     "default": { "filters": ["secrets", "pii"] },
+///     "internal": { "filters": ["secrets"], "disabled_detectors": ["jwt"] }
+///   },
+///   "api_keys": { "sk-team-a": "internal" },
+///   "detector_files": { "corp": "corp-detectors.json" }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GuardConfig {
+    pub profiles: HashMap<String, ProfileConfig>,
+    /// Maps the bearer token a client sends to the profile it gets.
+    pub api_keys: HashMap<String, String>,
+    /// Custom filters: filter name to detector file. Profiles list them in
+    /// `filters` like the built-in ones.
+    pub detector_files: HashMap<String, PathBuf>,
+}
+
+/// A named policy, built once at startup.
+pub struct Profile {
+    pub name: String,
+    pub filters: FilterPipeline,
+    pub input_dlp: Option<InputDlp>,
+    pub image_policy: ImagePolicy,
+    pub pseudonymizer: Option<Pseudonymizer>,
+    pub prompt_leak: Option<PromptLeakGuard>,
+    pub tool_policy: Option<ToolPolicy>,
+    pub block_response: BlockResponseConfig,
+    pub shadow: bool,
+    pub explain: bool,
+    pub admin: bool,
+}
+
+impl Profile {
+    pub fn from_config(name: &str, config: &ProfileConfig, custom: &CustomFilters) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            filters: FilterPipeline::with_options(&config.filters, &config.filter_options, custom)
+                .with_context(|| format!("profile '{}'", name))?,
+            input_dlp: match config.input_filters.filters.is_empty() {
+                true => None,
+                false => Some(InputDlp::new(&config.input_filters, &config.filter_options, custom)
+                    .with_context(|| format!("profile '{}' input filters", name))?),
+            },
+            image_policy: ImagePolicy::new(&config.images),
+            pseudonymizer: match config.pseudonymize.kinds.is_empty() {
+                true => None,
+                false => Some(Pseudonymizer::new(&config.pseudonymize, &config.filter_options)
+                    .with_context(|| format!("profile '{}'", name))?),
+            },
+            prompt_leak: config.prompt_leak.as_ref()
+                .map(PromptLeakGuard::new)
+                .transpose()
+                .with_context(|| format!("profile '{}'", name))?,
+            tool_policy: config.tools.as_ref()
+                .map(|tools| ToolPolicy::new(tools, &config.filter_options, custom))
+                .transpose()
+                .with_context(|| format!("profile '{}' tool policy", name))?,
+            block_response: config.block_response.clone(),
+            shadow: config.shadow,
+            explain: config.explain,
+            admin: config.admin,
+        })
+    }
+}
+
+pub struct Profiles {
+    profiles: HashMap<String, Arc<Profile>>,
+    api_keys: HashMap<String, String>,
+    custom: CustomFilters,
+    /// Each profile's filter options, to recheck against reloaded detectors.
+    options: HashMap<String, FilterOptions>,
+}
+
+impl Profiles {
+    pub fn from_config(mut config: GuardConfig) -> Result<Self> {
+        config.profiles.entry(DEFAULT_PROFILE.to_string()).or_default();
+
+        let custom = config.detector_files.iter()
+            .map(|(name, path)| {
+                if matches!(name.as_str(), "secrets" | "pii" | "links" | "commands" | "packages") {
+                    return Err(anyhow!("custom filter '{}' shadows a built-in filter", name));
+                }
+                Ok((name.clone(), Arc::new(CustomFilter::load(name, path)?)))
+            })
+            .collect::<Result<CustomFilters>>()?;
+
+        let profiles = config.profiles.iter()
+            .map(|(name, profile)| Ok((name.clone(), Arc::new(Profile::from_config(name, profile, &custom)?))))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        if let Some((_, profile)) = config.api_keys.iter().find(|(_, p)| !profiles.contains_key(*p)) {
+            return Err(anyhow!("API key mapped to unknown profile '{}'", profile));
+        }
+
+        let options = config.profiles.into_iter().map(|(name, profile)| (name, profile.filter_options)).collect();
+        Ok(Self { profiles, api_keys: config.api_keys, custom, options })
+    }
+
+    /// Loads the config file; relative detector file paths are resolved
+    /// against its directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let mut config: GuardConfig = serde_json::from_str(&data).with_context(|| format!("parsing {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        for detector_file in config.detector_files.values_mut() {
+            *detector_file = base.join(&*detector_file);
+        }
+        Self::from_config(config)
+    }
+
+    /// Re-reads every detector file. A file that fails to load keeps its
+    /// previous detectors; the others are still reloaded. If a profile's
+    /// `disabled_detectors` or `redaction_styles` would name a detector that
+    /// no longer exists, as `from_config` would refuse, every file keeps its
+    /// previous detectors.
+    pub fn reload_detectors(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut staged = HashMap::new();
+        for (name, filter) in &self.custom {
+            match filter.stage() {
+                Ok(detectors) => {
+                    staged.insert(name, detectors);
+                }
+                Err(e) => errors.push(format!("{:#}", e)),
+            }
+        }
+
+        let kinds: Vec<String> = self.custom.iter()
+            .flat_map(|(name, filter)| staged.get(name).map_or_else(|| filter.kinds(), |detectors| detectors.kinds()))
+            .collect();
+        for (name, options) in &self.options {
+            validate_options(options, &kinds)
+                .with_context(|| format!("profile '{}' does not fit the reloaded detectors; keeping the previous ones", name))?;
+        }
+
+        for (name, detectors) in staged {
+            self.custom[name].activate(detectors);
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!(errors.join("; "))),
+        }
+    }
+
+    /// Request body size that fits the largest image payload any profile
+    /// accepts, on top of the usual limit for the text.
+    pub fn max_body_bytes(&self) -> usize {
+        let images = self.profiles.values().map(|p| p.image_policy.max_encoded_bytes()).max().unwrap_or(0);
+        images.saturating_add(TEXT_BODY_BYTES)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Profile>> {
+        self.profiles.get(name).cloned()
+    }
+
+    pub fn default_profile(&self) -> Arc<Profile> {
+        self.profiles[DEFAULT_PROFILE].clone()
+    }
+
+    /// Profile for the request's bearer token, or the default profile.
+    pub fn for_request(&self, headers: &HeaderMap) -> Arc<Profile> {
+        bearer_key(headers)
+            .and_then(|key| self.api_keys.get(key))
+            .and_then(|name| self.get(name))
+            .unwrap_or_else(|| self.default_profile())
+    }
+}
+
+fn bearer_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Identity of the client behind a request: its profile and a digest of its
+/// API key (`anonymous` without one). Scopes per-client state so that one
+/// client cannot reach another's.
+pub fn client_id(profile: &Profile, headers: &HeaderMap) -> String {
+    match bearer_key(headers) {
+        Some(key) => format!("{}:{}", profile.name, &hex::encode(Sha256::digest(key.as_bytes()))[..16]),
+        None => format!("{}:anonymous", profile.name),
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::from_config(GuardConfig::default()).expect("built-in default profile is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profiles() -> Profiles {
+        let config: GuardConfig = serde_json::from_value(json!({
+            "profiles": { "internal": { "filters": ["secrets"] } },
+            "api_keys": { "sk-team-a": "internal" }
+        })).unwrap();
+        Profiles::from_config(config).unwrap()
+    }
+
+    #[test]
+    fn test_profile_selected_by_api_key() {
+        let profiles = profiles();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer sk-team-a".parse().unwrap());
+        assert_eq!(profiles.for_request(&headers).name, "internal");
+        assert_eq!(profiles.for_request(&headers).filters.names(), vec!["secrets"]);
+
+        headers.insert("authorization", "Bearer sk-unknown".parse().unwrap());
+        assert_eq!(profiles.for_request(&headers).name, DEFAULT_PROFILE);
+        assert_eq!(profiles.for_request(&HeaderMap::new()).filters.names(), vec!["secrets", "pii"]);
+    }
+
+    #[test]
+    fn test_reload_keeps_detectors_that_profiles_refer_to() {
+        let dir = std::env::temp_dir().join(format!("molt-guard-reload-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("corp.json");
+        std::fs::write(&path, json!({ "detectors": [{ "name": "host", "terms": ["db01"] }] }).to_string()).unwrap();
+        let config: GuardConfig = serde_json::from_value(json!({
+            "detector_files": { "corp": path },
+            "profiles": { "default": { "filters": ["corp"], "redaction_styles": { "host": "typed" } } }
+        })).unwrap();
+        let profiles = Profiles::from_config(config).unwrap();
+
+        std::fs::write(&path, json!({ "detectors": [{ "name": "server", "terms": ["db02"] }] }).to_string()).unwrap();
+        assert!(profiles.reload_detectors().is_err());
+        assert_eq!(profiles.default_profile().filters.apply("db01 db02").text, "[HOST_1] db02");
+
+        std::fs::write(&path, json!({ "detectors": [{ "name": "host", "terms": ["db02"] }] }).to_string()).unwrap();
+        profiles.reload_detectors().unwrap();
+        assert_eq!(profiles.default_profile().filters.apply("db01 db02").text, "db01 [HOST_1]");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        let unknown_profile: GuardConfig = serde_json::from_value(json!({ "api_keys": { "k": "missing" } })).unwrap();
+        assert!(Profiles::from_config(unknown_profile).is_err());
+
+        let unknown_filter: GuardConfig = serde_json::from_value(json!({ "profiles": { "p": { "filters": ["nope"] } } })).unwrap();
+        assert!(Profiles::from_config(unknown_filter).is_err());
+    }
+}
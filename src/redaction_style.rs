@@ -0,0 +1,207 @@
+use crate::filters::Finding;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key in `redaction_styles` that applies to every kind without its own entry.
+pub const DEFAULT_STYLE_KEY: &str = "default";
+
+/// How a finding is written back into the text.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionStyle {
+    /// The filter's fixed placeholder, e.g. `[PII_REDACTED]`.
+    #[default]
+    Placeholder,
+    /// `[EMAIL_1]`, `[EMAIL_2]`, ...; repeats of a value reuse its number.
+    Typed,
+    /// Everything but the last four characters hidden, e.g. `****4242`.
+    Mask,
+    /// `[EMAIL:3f9a0c1d]`, a salted hash that is stable for a given value.
+    Hash,
+    /// A fake value of the same shape, e.g. another valid-looking email.
+    Synthetic,
+}
+
+/// Per-kind redaction styles of one pipeline.
+#[derive(Clone, Default)]
+pub struct Redactor {
+    styles: HashMap<String, RedactionStyle>,
+    salt: Vec<u8>,
+}
+
+impl Redactor {
+    /// `salt` keys the `hash` and `synthetic` styles; without one, a random
+    /// salt is used, so their output cannot be correlated across restarts.
+    pub fn new(styles: HashMap<String, RedactionStyle>, salt: Option<&str>) -> Self {
+        let salt = match salt {
+            Some(salt) => salt.as_bytes().to_vec(),
+            None => uuid::Uuid::new_v4().as_bytes().to_vec(),
+        };
+        Self { styles, salt }
+    }
+
+    pub fn style(&self, kind: &str) -> RedactionStyle {
+        self.styles.get(kind)
+            .or_else(|| self.styles.get(DEFAULT_STYLE_KEY))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Replacement state for one body of text, so that numbering of typed
+    /// tokens starts over for every response.
+    pub fn session(&self) -> RedactionSession<'_> {
+        RedactionSession { redactor: self, numbers: HashMap::new(), counters: HashMap::new() }
+    }
+
+    fn digest(&self, value: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.salt).expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+pub struct RedactionSession<'a> {
+    redactor: &'a Redactor,
+    numbers: HashMap<(String, String), usize>,
+    counters: HashMap<String, usize>,
+}
+
+impl RedactionSession<'_> {
+    /// Replacement for `value`, the text of `finding`. `placeholder` is the
+    /// filter's own placeholder, used by the default style.
+    pub fn replace(&mut self, finding: &Finding, value: &str, placeholder: impl FnOnce() -> String) -> String {
+        let label = finding.kind.to_ascii_uppercase();
+        match self.redactor.style(&finding.kind) {
+            RedactionStyle::Placeholder => placeholder(),
+            RedactionStyle::Typed => {
+                let counter = self.counters.entry(finding.kind.clone()).or_default();
+                let number = *self.numbers.entry((finding.kind.clone(), value.to_string())).or_insert_with(|| {
+                    *counter += 1;
+                    *counter
+                });
+                format!("[{}_{}]", label, number)
+            }
+            RedactionStyle::Mask => mask(value),
+            RedactionStyle::Hash => format!("[{}:{}]", label, hex::encode(&self.redactor.digest(value)[..4])),
+            RedactionStyle::Synthetic => synthetic(&finding.kind, value, &self.redactor.digest(value)),
+        }
+    }
+}
+
+fn mask(value: &str) -> String {
+    let tail: Vec<char> = value.chars().filter(|c| c.is_alphanumeric()).collect();
+    if tail.len() <= 4 {
+        return "****".to_string();
+    }
+    format!("****{}", tail[tail.len() - 4..].iter().collect::<String>())
+}
+
+/// Replaces each digit and letter with one derived from `digest`, keeping
+/// case, separators and length; emails get a reserved domain and card numbers
+/// a valid Luhn digit so the result still passes for the original format.
+fn synthetic(kind: &str, value: &str, digest: &[u8]) -> String {
+    if kind == "email" {
+        return format!("user-{}@example.com", hex::encode(&digest[..3]));
+    }
+    let mut bytes = digest.iter().cycle();
+    let mut fake: String = value.chars()
+        .map(|c| {
+            let b = *bytes.next().unwrap_or(&0);
+            match c {
+                '0'..='9' => char::from(b'0' + b % 10),
+                'a'..='z' => char::from(b'a' + b % 26),
+                'A'..='Z' => char::from(b'A' + b % 26),
+                _ => c,
+            }
+        })
+        .collect();
+    if kind == "credit_card" {
+        // Keep the issuer digit so the number still reads as a card.
+        fake.replace_range(0..1, &value[..1]);
+        let check = luhn_check_digit(&fake);
+        if let Some(last) = fake.rfind(|c: char| c.is_ascii_digit()) {
+            fake.replace_range(last..last + 1, &check.to_string());
+        }
+    }
+    fake
+}
+
+/// The last digit that makes `number` Luhn-valid.
+fn luhn_check_digit(number: &str) -> u32 {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits[..digits.len() - 1].iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 0 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(kind: &str) -> Finding {
+        Finding { filter: "pii".to_string(), kind: kind.to_string(), start: 0, end: 0, confidence: 1.0 }
+    }
+
+    fn redactor(style: RedactionStyle) -> Redactor {
+        Redactor::new(HashMap::from([(DEFAULT_STYLE_KEY.to_string(), style)]), Some("salt"))
+    }
+
+    #[test]
+    fn test_typed_tokens_number_distinct_values() {
+        let redactor = redactor(RedactionStyle::Typed);
+        let mut session = redactor.session();
+        let email = finding("email");
+        let placeholder = String::new;
+        assert_eq!(session.replace(&email, "a@example.com", placeholder), "[EMAIL_1]");
+        assert_eq!(session.replace(&email, "b@example.com", placeholder), "[EMAIL_2]");
+        assert_eq!(session.replace(&email, "a@example.com", placeholder), "[EMAIL_1]");
+        assert_eq!(session.replace(&finding("phone"), "555-123-4567", placeholder), "[PHONE_1]");
+    }
+
+    #[test]
+    fn test_mask_and_hash() {
+        let masked = redactor(RedactionStyle::Mask);
+        let mut session = masked.session();
+        assert_eq!(session.replace(&finding("credit_card"), "4242 4242 4242 4242", String::new), "****4242");
+        assert_eq!(session.replace(&finding("ssn"), "1-2", String::new), "****");
+
+        let hashed = redactor(RedactionStyle::Hash);
+        let first = hashed.session().replace(&finding("email"), "a@example.com", String::new);
+        assert!(first.starts_with("[EMAIL:") && first.len() == "[EMAIL:]".len() + 8);
+        assert_eq!(hashed.session().replace(&finding("email"), "a@example.com", String::new), first);
+        let resalted = Redactor::new(HashMap::from([(DEFAULT_STYLE_KEY.to_string(), RedactionStyle::Hash)]), Some("other"));
+        assert_ne!(resalted.session().replace(&finding("email"), "a@example.com", String::new), first);
+    }
+
+    #[test]
+    fn test_synthetic_preserves_format() {
+        let redactor = redactor(RedactionStyle::Synthetic);
+        let mut session = redactor.session();
+
+        let email = session.replace(&finding("email"), "john.doe@corp.com", String::new);
+        assert!(email.starts_with("user-") && email.ends_with("@example.com"));
+
+        let phone = session.replace(&finding("phone"), "555-123-4567", String::new);
+        assert_ne!(phone, "555-123-4567");
+        assert!(phone.chars().enumerate().all(|(i, c)| if i == 3 || i == 7 { c == '-' } else { c.is_ascii_digit() }));
+
+        let card = session.replace(&finding("credit_card"), "4111 1111 1111 1111", String::new);
+        assert!(card.starts_with('4') && card.len() == 19);
+        let digits: String = card.chars().filter(|c| c.is_ascii_digit()).collect();
+        assert_eq!(luhn_check_digit(&digits), digits.chars().last().unwrap().to_digit(10).unwrap());
+    }
+
+    #[test]
+    fn test_per_kind_style_overrides_default() {
+        let redactor = Redactor::new(HashMap::from([("email".to_string(), RedactionStyle::Typed)]), None);
+        assert_eq!(redactor.style("email"), RedactionStyle::Typed);
+        assert_eq!(redactor.style("phone"), RedactionStyle::Placeholder);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::api_types::Message;
+use crate::classifier::Verdict;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Request header clients can use to name their conversation explicitly.
+pub const SESSION_HEADER: &str = "x-molt-guard-session";
+
+/// Thresholds for cumulative per-session risk.
+///
+/// Each turn adds the classifier score to the session's risk after decaying
+/// the previous total, so one borderline turn fades out while a steady run
+/// of them ("crescendo" attacks) keeps climbing.
+#[derive(Clone, Debug)]
+pub struct SessionPolicy {
+    /// Multiplier applied to the accumulated risk before each new turn.
+    pub decay: f32,
+    /// Score at which an unflagged turn still counts as a near miss.
+    pub near_miss: f32,
+    /// Risk at which near misses start being blocked.
+    pub elevate_at: f32,
+    /// Risk at which the whole session is blocked.
+    pub block_at: f32,
+    /// Sessions idle for longer than this are forgotten.
+    pub ttl: Duration,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self {
+            decay: 0.8,
+            near_miss: 0.3,
+            elevate_at: 1.0,
+            block_at: 2.5,
+            ttl: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionRisk {
+    pub risk: f32,
+    pub turns: u32,
+    pub near_misses: u32,
+    pub blocked: bool,
+}
+
+impl SessionRisk {
+    /// True once the session has accumulated enough risk that near misses are
+    /// treated as blocks, i.e. the effective sensitivity has been raised.
+    pub fn elevated(&self, policy: &SessionPolicy) -> bool {
+        self.risk >= policy.elevate_at
+    }
+}
+
+struct Entry {
+    risk: SessionRisk,
+    last_seen: Instant,
+}
+
+pub struct SessionTracker {
+    policy: SessionPolicy,
+    sessions: Mutex<HashMap<String, Entry>>,
+}
+
+impl SessionTracker {
+    pub fn new(policy: SessionPolicy) -> Self {
+        Self {
+            policy,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &SessionPolicy {
+        &self.policy
+    }
+
+    pub fn is_blocked(&self, session_id: &str) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session_id)
+            .is_some_and(|e| e.risk.blocked && e.last_seen.elapsed() < self.policy.ttl)
+    }
+
+    /// Folds a turn's verdict into the session and returns the updated risk.
+    pub fn record(&self, session_id: &str, verdict: &Verdict) -> SessionRisk {
+        let mut sessions = self.sessions.lock().unwrap();
+        let ttl = self.policy.ttl;
+        sessions.retain(|_, e| e.last_seen.elapsed() < ttl);
+
+        let entry = sessions.entry(session_id.to_string()).or_insert_with(|| Entry {
+            risk: SessionRisk::default(),
+            last_seen: Instant::now(),
+        });
+        entry.last_seen = Instant::now();
+
+        let risk = &mut entry.risk;
+        risk.risk = risk.risk * self.policy.decay + verdict.score;
+        risk.turns += 1;
+        if !verdict.flagged && verdict.score >= self.policy.near_miss {
+            risk.near_misses += 1;
+        }
+        if risk.risk >= self.policy.block_at {
+            risk.blocked = true;
+        }
+        risk.clone()
+    }
+}
+
+/// Derives a stable session id from the client (see `profiles::client_id`)
+/// and the opening of a conversation: every message up to and including the
+/// first user turn. Later turns append to the history, so this prefix stays
+/// the same for the whole conversation. Clients that open the same way still
+/// get separate sessions.
+pub fn session_id_from_messages(client: &str, messages: &[Message]) -> Option<String> {
+    let first_user = messages.iter().position(|m| m.role == "user")?;
+    let mut hasher = DefaultHasher::new();
+    client.hash(&mut hasher);
+    for message in &messages[..=first_user] {
+        message.role.hash(&mut hasher);
+        message.content.hash(&mut hasher);
+    }
+    Some(format!("conv-{:016x}", hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string(), ..Message::default() }
+    }
+
+    #[test]
+    fn test_session_id_stable_across_turns() {
+        let first = vec![message("system", "Be nice"), message("user", "Hi")];
+        let mut later = first.clone();
+        later.push(message("assistant", "Hello!"));
+        later.push(message("user", "Tell me more"));
+
+        assert_eq!(session_id_from_messages("default:a", &first), session_id_from_messages("default:a", &later));
+        assert_ne!(session_id_from_messages("default:a", &first), session_id_from_messages("default:a", &[message("user", "Hey")]));
+        assert_ne!(session_id_from_messages("default:a", &first), session_id_from_messages("default:b", &first));
+    }
+
+    #[test]
+    fn test_risk_accumulates_until_blocked() {
+        let tracker = SessionTracker::new(SessionPolicy::default());
+        let near_miss = Verdict { flagged: false, score: 0.6, reason: String::new() };
+
+        let risk = tracker.record("s", &near_miss);
+        assert!(!risk.elevated(tracker.policy()));
+        assert_eq!(risk.near_misses, 1);
+
+        let risk = tracker.record("s", &near_miss);
+        assert!(risk.elevated(tracker.policy()));
+        assert!(!tracker.is_blocked("s"));
+
+        for _ in 0..10 {
+            tracker.record("s", &near_miss);
+        }
+        assert!(tracker.is_blocked("s"));
+        assert!(!tracker.is_blocked("other"));
+    }
+
+    #[test]
+    fn test_benign_turns_decay_risk() {
+        let tracker = SessionTracker::new(SessionPolicy::default());
+        tracker.record("s", &Verdict { flagged: false, score: 0.9, reason: String::new() });
+        let mut risk = SessionRisk::default();
+        for _ in 0..10 {
+            risk = tracker.record("s", &Verdict::safe());
+        }
+        assert!(risk.risk < 0.1);
+    }
+}
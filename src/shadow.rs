@@ -0,0 +1,148 @@
+use crate::explain::{Explanation, StageVerdict};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// `blocked`/`modified` counts of one stage.
+type StageCounts = BTreeMap<&'static str, u64>;
+
+/// Counts, per profile and stage, of what shadow-mode profiles would have
+/// done. Exposed on `/molt-guard/shadow`.
+#[derive(Default)]
+pub struct ShadowMeter {
+    counts: Mutex<BTreeMap<String, BTreeMap<String, StageCounts>>>,
+}
+
+impl ShadowMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, profile: &str, stage: &str, verdict: StageVerdict, reason: &str) {
+        let key = match verdict {
+            StageVerdict::Passed => return,
+            StageVerdict::Modified => "modified",
+            StageVerdict::Blocked => "blocked",
+        };
+        println!("!!! SHADOW: profile {} would have {} the traffic at {}: {}", profile, key, stage, reason);
+        let mut counts = self.counts.lock().unwrap();
+        *counts.entry(profile.to_string()).or_default()
+            .entry(stage.to_string()).or_default()
+            .entry(key).or_default() += 1;
+    }
+
+    /// `{"profile": {"stage": {"blocked": n, "modified": n}}}`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::to_value(&*self.counts.lock().unwrap()).unwrap_or_default()
+    }
+}
+
+/// How the policy stages of one request are run and where their outcomes go.
+#[derive(Clone, Copy)]
+pub struct Audit<'a> {
+    pub profile: &'a str,
+    /// Whether stages may block or alter traffic. Off in shadow mode and
+    /// for dry runs.
+    pub enforce: bool,
+    /// Set in shadow mode.
+    pub meter: Option<&'a ShadowMeter>,
+    /// Set if the client asked for an explanation.
+    pub explain: Option<&'a Explanation>,
+}
+
+impl<'a> Audit<'a> {
+    /// Enforces every stage and reports nothing.
+    pub fn enforcing(profile: &'a str) -> Self {
+        Self { profile, enforce: true, meter: None, explain: None }
+    }
+
+    fn report(&self, stage: &str, verdict: StageVerdict, reason: Option<&str>) {
+        if let Some(meter) = self.meter {
+            meter.record(self.profile, stage, verdict, reason.unwrap_or("content changed"));
+        }
+        if let Some(explain) = self.explain {
+            explain.record(stage, verdict, reason);
+        }
+    }
+}
+
+/// Applies a policy `step` to `value`. When the audit does not enforce, the
+/// step runs on a copy instead, what it would have done is reported, and it
+/// never fails.
+pub fn apply<T: Clone + PartialEq>(audit: Audit, stage: &str, value: &mut T, step: impl FnOnce(&mut T) -> Result<()>) -> Result<()> {
+    if audit.enforce && audit.explain.is_none() {
+        return step(value);
+    }
+    let mut copy = value.clone();
+    let result = step(&mut copy);
+    match &result {
+        Err(e) => audit.report(stage, StageVerdict::Blocked, Some(&e.to_string())),
+        Ok(()) if copy != *value => audit.report(stage, StageVerdict::Modified, None),
+        Ok(()) => audit.report(stage, StageVerdict::Passed, None),
+    }
+    if audit.enforce {
+        result?;
+        *value = copy;
+    }
+    Ok(())
+}
+
+/// `apply` for a step that cannot fail.
+pub fn rewrite<T: Clone + PartialEq>(audit: Audit, stage: &str, value: &mut T, step: impl FnOnce(&mut T)) {
+    let _ = apply(audit, stage, value, |value| {
+        step(value);
+        Ok(())
+    });
+}
+
+/// A check's `result`, reported, or `Ok` when the audit does not enforce.
+pub fn verdict(audit: Audit, stage: &str, result: Result<()>) -> Result<()> {
+    match &result {
+        Err(e) => audit.report(stage, StageVerdict::Blocked, Some(&e.to_string())),
+        Ok(()) => audit.report(stage, StageVerdict::Passed, None),
+    }
+    if audit.enforce { result } else { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn redact(text: &mut String) -> Result<()> {
+        *text = text.replace("secret", "[REDACTED]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_enforces_without_meter() {
+        let mut text = "a secret".to_string();
+        apply(Audit::enforcing("p"), "filters", &mut text, redact).unwrap();
+        assert_eq!(text, "a [REDACTED]");
+        assert!(apply(Audit::enforcing("p"), "filters", &mut text, |_| Err(anyhow!("no"))).is_err());
+
+        // Explaining does not change what is enforced.
+        let explain = Explanation::new("p", false, false);
+        let audit = Audit { explain: Some(&explain), ..Audit::enforcing("p") };
+        let mut text = "a secret".to_string();
+        apply(audit, "filters", &mut text, redact).unwrap();
+        assert_eq!(text, "a [REDACTED]");
+        assert_eq!(explain.to_json()["stages"][0]["verdict"], "modified");
+    }
+
+    #[test]
+    fn test_shadow_records_without_altering() {
+        let meter = ShadowMeter::new();
+        let audit = Audit { enforce: false, meter: Some(&meter), ..Audit::enforcing("p") };
+        let mut text = "a secret".to_string();
+        apply(audit, "filters", &mut text, redact).unwrap();
+        apply(audit, "filters", &mut text, |_| Err(anyhow!("Security block: no"))).unwrap();
+        apply(audit, "filters", &mut "clean".to_string(), redact).unwrap();
+        verdict(audit, "guard", Err(anyhow!("Security block: injection"))).unwrap();
+        assert_eq!(text, "a secret");
+
+        assert_eq!(meter.snapshot(), serde_json::json!({
+            "p": { "filters": { "blocked": 1, "modified": 1 }, "guard": { "blocked": 1 } }
+        }));
+    }
+}